@@ -0,0 +1,59 @@
+//! Compares loading a large file with `load_bytes` and `load_bytes_borrowed`.
+//!
+//! Run with `cargo bench`.
+
+extern crate dot_vox;
+
+use dot_vox::{load_bytes, load_bytes_borrowed, Model, Size, Voxel};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+fn large_file() -> Vec<u8> {
+    let mut data = load_bytes(include_bytes!("../src/resources/placeholder-with-materials.vox"))
+        .expect("placeholder should load");
+    let size = Size { x: 256, y: 256, z: 64 };
+    data.models = (0..4)
+        .map(|model| Model {
+            size,
+            voxels: (0..size.x * size.y * size.z)
+                .filter(|index| index % 4 == model)
+                .map(|index| Voxel {
+                    x: (index % size.x) as u8,
+                    y: (index / size.x % size.y) as u8,
+                    z: (index / (size.x * size.y)) as u8,
+                    i: (index % 255) as u8,
+                })
+                .collect(),
+        })
+        .collect();
+    let mut bytes = Vec::new();
+    data.write_vox(&mut bytes).expect("writing to a Vec shouldn't fail");
+    bytes
+}
+
+fn time<F: FnMut() -> usize>(name: &str, mut load: F) -> Duration {
+    let mut voxels = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        voxels += load();
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{:<10} {:>10.3?} per load ({} voxels)", name, elapsed, voxels / ITERATIONS as usize);
+    elapsed
+}
+
+fn main() {
+    let bytes = large_file();
+    println!("Loading {} bytes, {} times each", bytes.len(), ITERATIONS);
+    let owned = time("owned", || {
+        let data = load_bytes(&bytes).expect("file should load");
+        data.models.iter().map(|model| model.voxels.len()).sum()
+    });
+    let borrowed = time("borrowed", || {
+        let data = load_bytes_borrowed(&bytes).expect("file should load");
+        data.models.iter().map(|model| model.voxels.len()).sum()
+    });
+    println!("borrowed takes {:.1}% of the time of owned",
+             100.0 * borrowed.as_secs_f64() / owned.as_secs_f64());
+}
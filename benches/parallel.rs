@@ -0,0 +1,65 @@
+//! Compares loading a file with hundreds of models on a single thread and on rayon's global
+//! thread pool.
+//!
+//! Run with `cargo bench --bench parallel --features rayon`.
+
+extern crate dot_vox;
+extern crate rayon;
+
+use dot_vox::{load_bytes, Model, Size, Voxel};
+use rayon::ThreadPoolBuilder;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 10;
+const MODELS: u32 = 500;
+
+fn many_models() -> Vec<u8> {
+    let mut data = load_bytes(include_bytes!("../src/resources/placeholder-with-materials.vox"))
+        .expect("placeholder should load");
+    let size = Size { x: 32, y: 32, z: 32 };
+    data.models = (0..MODELS)
+        .map(|model| Model {
+            size,
+            voxels: (0..size.x * size.y * size.z)
+                .filter(|index| (index + model) % 2 == 0)
+                .map(|index| Voxel {
+                    x: (index % size.x) as u8,
+                    y: (index / size.x % size.y) as u8,
+                    z: (index / (size.x * size.y)) as u8,
+                    i: ((index + model) % 255) as u8,
+                })
+                .collect(),
+        })
+        .collect();
+    let mut bytes = Vec::new();
+    data.write_vox(&mut bytes).expect("writing to a Vec shouldn't fail");
+    bytes
+}
+
+fn time<F: FnMut() -> usize>(name: &str, mut load: F) -> Duration {
+    let mut voxels = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        voxels += load();
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{:<16} {:>10.3?} per load ({} voxels)", name, elapsed, voxels / ITERATIONS as usize);
+    elapsed
+}
+
+fn load_voxels(bytes: &[u8]) -> usize {
+    let data = load_bytes(bytes).expect("file should load");
+    data.models.iter().map(|model| model.voxels.len()).sum()
+}
+
+fn main() {
+    let bytes = many_models();
+    println!("Loading {} models in {} bytes, {} times each, with up to {} threads",
+             MODELS, bytes.len(), ITERATIONS, rayon::current_num_threads());
+    let single_thread = ThreadPoolBuilder::new().num_threads(1).build().expect("pool should build");
+    let sequential = single_thread.install(|| time("single thread", || load_voxels(&bytes)));
+    let parallel = time("global pool", || load_voxels(&bytes));
+    println!("the global pool takes {:.1}% of the time of a single thread, a {:.1}x speedup",
+             100.0 * parallel.as_secs_f64() / sequential.as_secs_f64(),
+             sequential.as_secs_f64() / parallel.as_secs_f64());
+}
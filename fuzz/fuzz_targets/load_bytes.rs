@@ -0,0 +1,17 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate dot_vox;
+
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok(data) = dot_vox::load_bytes(bytes) {
+        data.validate();
+        data.world_voxels().count();
+        let _ = data.scene_graph.try_collapse_to_vec();
+        data.write_vox(Vec::new()).unwrap();
+    }
+    if let Ok(data) = dot_vox::load_bytes_borrowed(bytes) {
+        data.to_owned();
+    }
+    let _ = dot_vox::load_header(bytes);
+});
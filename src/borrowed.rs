@@ -0,0 +1,314 @@
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use byteorder::{ByteOrder, LittleEndian};
+use nom::types::CompleteByteSlice;
+use error::ParseError;
+use parser::{self, le_count, le_u32, Chunk, LocatedChunk};
+use std::borrow::Cow;
+use std::slice;
+use {Color, DotVoxData, Material, model, palette, RawVoxel, Size, ValidationIssue, Voxel};
+
+/// Container for .vox file data which borrows from the buffer it was parsed from
+///
+/// Models, the palette and materials are read in place. Every other chunk is kept as a
+/// `RawChunkRef`, and only parsed when converting to a `DotVoxData` with `to_owned`. In
+/// particular the scene graph (nTRN, nGRP and nSHP chunks) and layers (LAYR chunks) are only
+/// available after `to_owned`.
+#[derive(Debug, PartialEq)]
+pub struct DotVoxDataRef<'a> {
+    /// The version number of the .vox file.
+    pub version: u32,
+    /// A Vec of all the models contained within this file.
+    pub models: Vec<ModelRef<'a>>,
+    /// The contents of the RGBA chunk (four bytes per colour, in the order of
+    /// `DotVoxData::palette`), or `None` if the file uses `DEFAULT_PALETTE`.
+    pub palette: Option<&'a [u8]>,
+    /// A Vec containing all the MATL materials set
+    pub materials: Vec<MaterialRef<'a>>,
+    /// Every other chunk within the MAIN chunk, in the order they appear in the file
+    pub chunks: Vec<RawChunkRef<'a>>,
+    /// Problems with the models in the file which were worked around, as in
+    /// `DotVoxData::model_issues`.
+    pub model_issues: Vec<ValidationIssue>,
+}
+
+/// A renderable voxel Model, borrowing its voxels from an XYZI chunk
+///
+/// The voxels are stored as in the file, so their palette indices are one more than those of
+/// `Voxel` and `Model` (which index `DotVoxData::palette` directly). Use `iter_voxels` to read
+/// them as `Voxel`s.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelRef<'a> {
+    /// The size of the model in voxels
+    pub size: Size,
+    /// The voxels to be displayed, exactly as stored in the file. `RawVoxel::i` runs from 1 to
+    /// 255, so is one more than the palette index.
+    pub voxels: &'a [RawVoxel],
+}
+
+/// A material used to render this model, borrowing its properties from a MATL chunk
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialRef<'a> {
+    /// The Material's ID
+    pub id: u32,
+    /// Properties of the material, in the order they appear in the file. These only need to be
+    /// copied if they contain invalid UTF-8.
+    pub properties: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+/// A chunk which has not been parsed
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawChunkRef<'a> {
+    /// The four character ID of the chunk
+    pub id: &'a str,
+    /// The content of the chunk
+    pub content: &'a [u8],
+    /// The chunk's children, still encoded
+    pub children: &'a [u8],
+    /// The index of the chunk among the children of the MAIN chunk, as in `RawChunk::position`
+    pub position: usize,
+}
+
+impl<'a> DotVoxDataRef<'a> {
+    /// The colour at `index` in the palette, as in `DotVoxData::color`.
+    pub fn color(&self, index: u8) -> Color {
+        match self.palette {
+            Some(palette) => palette
+                .chunks(4)
+                .nth(index as usize)
+                .filter(|colour| colour.len() == 4)
+                .map(|colour| Color { r: colour[0], g: colour[1], b: colour[2], a: colour[3] })
+                .unwrap_or_default(),
+            None => Color::from(palette::DEFAULT_PALETTE[index as usize]),
+        }
+    }
+
+    /// Copies this data into a `DotVoxData`, parsing the remaining chunks, so that the result
+    /// is the same as loading the file with `load_bytes`.
+    pub fn to_owned(&self) -> DotVoxData {
+        let mut children = Vec::with_capacity(self.models.len() * 2 + self.chunks.len() + 1);
+        for model in &self.models {
+            children.push(Chunk::Size(model.size));
+            children.push(Chunk::Voxels(model.iter_voxels().collect()));
+        }
+        if let Some(palette) = self.palette {
+            children.push(parser::build_chunk(
+                "RGBA".to_owned(),
+                CompleteByteSlice(palette),
+                0,
+                CompleteByteSlice(&[]),
+            ));
+        }
+        for material in &self.materials {
+            children.push(Chunk::Material(material.to_owned()));
+        }
+        for chunk in &self.chunks {
+            let built = parser::build_chunk(
+                chunk.id.to_owned(),
+                CompleteByteSlice(chunk.content),
+                chunk.children.len() as u32,
+                CompleteByteSlice(chunk.children),
+            );
+            children.push(parser::place_chunk(built, chunk.position));
+        }
+        // Every model is complete by now, so the issues found while parsing are copied over
+        let mut data = parser::map_chunk_to_data(self.version, Chunk::Main(children));
+        data.model_issues = self.model_issues.clone();
+        data
+    }
+}
+
+impl<'a> ModelRef<'a> {
+    /// The voxels of this model with their palette indices adjusted as in `Model::voxels`, so
+    /// that `Voxel::i` indexes `DotVoxData::palette` (and `DotVoxDataRef::color`) directly.
+    pub fn iter_voxels(&self) -> impl Iterator<Item = Voxel> + 'a {
+        self.voxels.iter().map(|&voxel| voxel.into())
+    }
+}
+
+impl<'a> MaterialRef<'a> {
+    /// The value of the named property, if set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .rev()
+            .find(|&(name, _)| name == key)
+            .map(|(_, value)| value.as_ref())
+    }
+
+    /// Copies this material into a `Material`.
+    pub fn to_owned(&self) -> Material {
+        Material {
+            id: self.id,
+            properties: self.properties
+                .iter()
+                .map(|(key, value)| (key.clone().into_owned(), value.clone().into_owned()))
+                .collect(),
+        }
+    }
+}
+
+pub fn parse_vox_file(input: &[u8]) -> Result<DotVoxDataRef<'_>, ParseError> {
+    let (version, main) = parser::read_vox_file(input)?;
+    map_chunk_to_data(version, main)
+}
+
+named!(parse_raw_voxels <CompleteByteSlice<'_>, &[RawVoxel]>, do_parse!(
+    num_voxels: le_u32 >>
+    voxels: take!((num_voxels as usize).saturating_mul(4)) >>
+    (raw_voxels(voxels.0))
+));
+
+named!(parse_string <CompleteByteSlice, Cow<str>>, do_parse!(
+    count: le_u32 >>
+    buffer: take!(count) >>
+    (String::from_utf8_lossy(buffer.0))
+));
+
+named!(parse_material <CompleteByteSlice, MaterialRef>, do_parse!(
+    id: le_u32 >>
+    count: call!(le_count, 8) >>
+    properties: many_m_n!(count, count, tuple!(parse_string, parse_string)) >>
+    (MaterialRef { id, properties })
+));
+
+fn raw_voxels(bytes: &[u8]) -> &[RawVoxel] {
+    // RawVoxel is four u8s with repr(C), so it has the same size (and alignment) as 4 bytes.
+    unsafe { slice::from_raw_parts(bytes.as_ptr() as *const RawVoxel, bytes.len() / 4) }
+}
+
+fn map_chunk_to_data(version: u32, main: LocatedChunk<'_>) -> Result<DotVoxDataRef<'_>, ParseError> {
+    let mut data = DotVoxDataRef {
+        version,
+        models: vec![],
+        palette: None,
+        materials: vec![],
+        chunks: vec![],
+        model_issues: vec![],
+    };
+    if main.id != "MAIN" {
+        debug!("Expected MAIN chunk, got {:?}", main.id);
+        return Ok(data);
+    }
+    let mut size_holder: Option<Size> = None;
+    let chunks = main.read_children()?;
+    for chunk in &chunks {
+        parser::check_content(chunk)?;
+    }
+    let mut declared_models: Option<u32> = None;
+    for (position, chunk) in chunks.into_iter().enumerate() {
+        let content = CompleteByteSlice(chunk.content);
+        // Models are found as in `parser::map_chunk_to_data`, with empty models in place of any
+        // missing SIZE or XYZI chunks
+        let size = size_holder.take();
+        match size {
+            Some(size) if !(chunk.id == "XYZI" && chunk.children.is_empty()) => {
+                data.model_issues.push(ValidationIssue::MissingVoxels { model: data.models.len() });
+                data.models.push(ModelRef { size, voxels: &[] });
+            }
+            _ => (),
+        }
+        let parsed = match (chunk.id, chunk.children.is_empty()) {
+            ("SIZE", true) => model::parse_size(content)
+                .map(|(_, size)| size_holder = Some(size))
+                .is_ok(),
+            ("XYZI", true) => parse_raw_voxels(content)
+                .map(|(_, voxels)| match size {
+                    Some(size) => data.models.push(ModelRef { size, voxels }),
+                    None => {
+                        data.model_issues.push(ValidationIssue::MissingSize { model: data.models.len() });
+                        data.models.push(ModelRef { size: Size { x: 0, y: 0, z: 0 }, voxels: &[] });
+                    }
+                })
+                .is_ok(),
+            ("PACK", true) => {
+                declared_models.get_or_insert(LittleEndian::read_u32(chunk.content));
+                true
+            }
+            ("RGBA", true) => {
+                data.palette = Some(chunk.content);
+                true
+            }
+            ("MATL", true) => parse_material(content)
+                .map(|(_, material)| data.materials.push(material))
+                .is_ok(),
+            _ => false,
+        };
+        if !parsed {
+            data.chunks.push(RawChunkRef { id: chunk.id, content: chunk.content, children: chunk.children, position });
+        }
+    }
+    if let Some(size) = size_holder {
+        data.model_issues.push(ValidationIssue::MissingVoxels { model: data.models.len() });
+        data.models.push(ModelRef { size, voxels: &[] });
+    }
+    match declared_models {
+        Some(declared) if declared as usize != data.models.len() => {
+            data.model_issues.push(ValidationIssue::ModelCountMismatch { declared, found: data.models.len() });
+        }
+        _ => (),
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voxels_are_read_in_place() {
+        let bytes = include_bytes!("resources/placeholder.vox");
+        let data = parse_vox_file(bytes).unwrap();
+        assert_eq!(data.models.len(), 1);
+        let voxels = data.models[0].voxels;
+        assert_eq!(voxels[0], RawVoxel { x: 0, y: 0, z: 0, i: 226 });
+        assert_eq!(data.models[0].iter_voxels().next(), Some(Voxel { x: 0, y: 0, z: 0, i: 225 }));
+        let owned = parser::parse_vox_file(bytes).unwrap();
+        assert!(data.models[0].iter_voxels().eq(owned.models[0].voxels.iter().cloned()));
+        let start = bytes.as_ptr() as usize;
+        let voxels_start = voxels.as_ptr() as usize;
+        assert!(voxels_start > start && voxels_start < start + bytes.len());
+    }
+
+    #[test]
+    fn material_properties_are_borrowed() {
+        let bytes = include_bytes!("resources/placeholder-with-materials.vox");
+        let data = parse_vox_file(bytes).unwrap();
+        let material = data.materials.iter().find(|material| material.id == 216).unwrap();
+        assert_eq!(material.get("_type"), Some("_metal"));
+        assert_eq!(material.get("_missing"), None);
+        assert!(material.properties.iter().all(|property| {
+            matches!(property, (Cow::Borrowed(_), Cow::Borrowed(_)))
+        }));
+    }
+
+    #[test]
+    fn palette_colours_are_decoded() {
+        let bytes = include_bytes!("resources/index-map.vox");
+        let data = parse_vox_file(bytes).unwrap();
+        assert_eq!(data.color(0), Color::from(0xff00_0000));
+        assert_eq!(data.color(255), Color::from(0xff00_00ff));
+
+        let bytes = include_bytes!("resources/placeholder.vox");
+        let data = parse_vox_file(bytes).unwrap();
+        assert_eq!(data.color(7), Color::from(palette::DEFAULT_PALETTE[7]));
+    }
+
+    fn assert_same_as_owned(bytes: &[u8]) {
+        let borrowed = parse_vox_file(bytes).unwrap();
+        let owned = parser::parse_vox_file(bytes).unwrap();
+        assert_eq!(borrowed.to_owned(), owned);
+    }
+
+    #[test]
+    fn converting_to_owned_matches_the_owned_parser() {
+        assert_same_as_owned(include_bytes!("resources/placeholder.vox"));
+        assert_same_as_owned(include_bytes!("resources/placeholder-with-materials.vox"));
+        assert_same_as_owned(include_bytes!("resources/two-model-scene.vox"));
+        assert_same_as_owned(include_bytes!("resources/three-layers.vox"));
+        assert_same_as_owned(include_bytes!("resources/cameras.vox"));
+        assert_same_as_owned(include_bytes!("resources/legacy-materials.vox"));
+        assert_same_as_owned(include_bytes!("resources/index-map.vox"));
+        assert_same_as_owned(include_bytes!("resources/palette-notes.vox"));
+    }
+}
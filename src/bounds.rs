@@ -0,0 +1,171 @@
+use model::world_position;
+use {DotVoxData, Model, Size, Transform};
+
+/// An axis-aligned box of voxels, from `min` to `max` inclusive along each axis
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BoundingBox {
+    /// The position of the voxel with the smallest coordinates.
+    pub min: [i32; 3],
+    /// The position of the voxel with the largest coordinates.
+    pub max: [i32; 3],
+}
+
+impl BoundingBox {
+    /// A box containing just the given position.
+    pub fn from_position(position: [i32; 3]) -> Self {
+        BoundingBox { min: position, max: position }
+    }
+
+    /// The number of voxels the box spans along each axis.
+    pub fn size(&self) -> [u64; 3] {
+        let mut size = [0; 3];
+        for (axis, size) in size.iter_mut().enumerate() {
+            *size = (self.max[axis] as i64 - self.min[axis] as i64 + 1) as u64;
+        }
+        size
+    }
+
+    /// The centre of the box, treating each voxel as a unit cube whose smallest corner is at its
+    /// position.
+    pub fn center(&self) -> [f32; 3] {
+        let mut center = [0.0; 3];
+        for (axis, center) in center.iter_mut().enumerate() {
+            *center = ((self.min[axis] as f64 + self.max[axis] as f64 + 1.0) / 2.0) as f32;
+        }
+        center
+    }
+
+    /// The smallest box containing both this box and `other`.
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        let mut union = *self;
+        for axis in 0..3 {
+            union.min[axis] = union.min[axis].min(other.min[axis]);
+            union.max[axis] = union.max[axis].max(other.max[axis]);
+        }
+        union
+    }
+
+    /// Grows the box to contain `position`.
+    fn include(&mut self, position: [i32; 3]) {
+        *self = self.union(&BoundingBox::from_position(position));
+    }
+
+    /// Positions this box, in the coordinates of a model with the given size, in the world as
+    /// described in `Model::world_voxels`. The transformation maps the model's voxels onto world
+    /// voxels, so the result is bounded by the eight corners of the box.
+    fn transformed(&self, size: Size, transform: &Transform) -> BoundingBox {
+        let corner = |index: usize| {
+            let mut corner = self.min;
+            for (axis, corner) in corner.iter_mut().enumerate() {
+                if index & (1 << axis) != 0 {
+                    *corner = self.max[axis];
+                }
+            }
+            world_position(size, transform, corner)
+        };
+        let mut transformed = BoundingBox::from_position(corner(0));
+        for index in 1..8 {
+            transformed.include(corner(index));
+        }
+        transformed
+    }
+}
+
+impl Model {
+    /// The box containing every voxel of this model, in the model's own coordinates, or `None`
+    /// if the model has no voxels.
+    ///
+    /// This is computed from the voxels themselves, so may be smaller than `size`.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let mut voxels = self.voxels.iter().map(|voxel| [voxel.x as i32, voxel.y as i32, voxel.z as i32]);
+        let mut bounds = BoundingBox::from_position(voxels.next()?);
+        for position in voxels {
+            bounds.include(position);
+        }
+        Some(bounds)
+    }
+}
+
+/// Finds the box containing every voxel in the scene, as described in
+/// `DotVoxData::scene_bounding_box`.
+pub fn scene_bounding_box(data: &DotVoxData) -> Option<BoundingBox> {
+    data.scene
+        .iter()
+        .filter_map(|(transform, model_id)| {
+            let model = data.models.get(*model_id)?;
+            Some(model.bounding_box()?.transformed(model.size, transform))
+        })
+        .fold(None, |bounds: Option<BoundingBox>, model_bounds| match bounds {
+            Some(bounds) => Some(bounds.union(&model_bounds)),
+            None => Some(model_bounds),
+        })
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use {load, Voxel};
+
+    fn model() -> Model {
+        Model {
+            size: Size { x: 5, y: 3, z: 1 },
+            voxels: vec![Voxel { x: 0, y: 0, z: 0, i: 1 }, Voxel { x: 3, y: 1, z: 0, i: 2 }],
+        }
+    }
+
+    fn data(models: Vec<Model>, scene: Vec<(Transform, usize)>) -> DotVoxData {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        data.models = models;
+        data.scene = scene;
+        data
+    }
+
+    #[test]
+    fn model_boxes_cover_the_voxels_rather_than_the_size() {
+        let bounds = model().bounding_box().unwrap();
+        assert_eq!(bounds, BoundingBox { min: [0, 0, 0], max: [3, 1, 0] });
+        assert_eq!(bounds.size(), [4, 2, 1]);
+        assert_eq!(bounds.center(), [2.0, 1.0, 0.5]);
+        assert_eq!(Model { size: Size { x: 1, y: 1, z: 1 }, voxels: vec![] }.bounding_box(), None);
+    }
+
+    #[test]
+    fn boxes_can_be_combined() {
+        let a = BoundingBox { min: [0, -4, 2], max: [1, 1, 3] };
+        let b = BoundingBox { min: [-2, 0, 5], max: [0, 7, 5] };
+        assert_eq!(a.union(&b), BoundingBox { min: [-2, -4, 2], max: [1, 7, 5] });
+        assert_eq!(a.union(&b), b.union(&a));
+        assert_eq!(BoundingBox::from_position([i32::MIN; 3]).union(&BoundingBox::from_position([i32::MAX; 3])).size(),
+                   [1 << 32; 3]);
+    }
+
+    #[test]
+    fn rotating_about_z_swaps_the_x_and_y_extents() {
+        let rotate_z = Transform { t: [10, 20, 30], r: [[0, -1, 0], [1, 0, 0], [0, 0, 1]] };
+        let data = data(vec![model()], vec![(rotate_z, 0)]);
+        let bounds = data.scene_bounding_box().unwrap();
+        assert_eq!(bounds.size(), [2, 4, 1]);
+        assert_eq!(bounds, BoundingBox { min: [10, 18, 30], max: [11, 21, 30] });
+    }
+
+    #[test]
+    fn scene_boxes_match_the_world_voxels() {
+        for file in &["two-model-scene.vox", "three-layers.vox", "placeholder.vox"] {
+            let data = load(&format!("src/resources/{}", file)).unwrap();
+            let mut voxels = data.world_voxels().map(|voxel| [voxel.x, voxel.y, voxel.z]);
+            let mut expected = BoundingBox::from_position(voxels.next().unwrap());
+            for position in voxels {
+                expected.include(position);
+            }
+            assert_eq!(data.scene_bounding_box(), Some(expected), "{}", file);
+        }
+    }
+
+    #[test]
+    fn empty_and_missing_models_have_no_box() {
+        let empty = Model { size: Size { x: 1, y: 1, z: 1 }, voxels: vec![] };
+        let data = data(vec![empty], vec![(Transform::default(), 0), (Transform::default(), 3)]);
+        assert_eq!(data.scene_bounding_box(), None);
+    }
+}
@@ -0,0 +1,114 @@
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use nom::types::CompleteByteSlice;
+use ::parser::{le_u32, parse_dict, Dict};
+
+/*
+Camera Chunk : "rCAM"
+
+int32	: camera id
+DICT	: camera attribute
+	  (_mode : string)
+	  (_focus : vec(3))
+	  (_angle : vec(3))
+	  (_radius : int)
+	  (_frustum : float)
+	  (_fov : int)
+*/
+
+/// A camera saved in the .vox file
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Camera {
+    /// The camera's ID.
+    pub id: u32,
+    /// Attributes of the camera, mapped by attribute name.
+    pub attributes: Dict,
+}
+
+impl Camera {
+    /// The projection mode of the camera (e.g. `pers` for perspective, or `orth` for
+    /// orthographic).
+    pub fn mode(&self) -> Option<&str> {
+        self.attributes.get("_mode").map(String::as_str)
+    }
+    /// The point the camera is looking at.
+    pub fn focus(&self) -> Option<[f32; 3]> {
+        self.vector("_focus")
+    }
+    /// The orientation of the camera, as angles in degrees.
+    pub fn angle(&self) -> Option<[f32; 3]> {
+        self.vector("_angle")
+    }
+    /// The distance of the camera from its focus.
+    pub fn radius(&self) -> Option<f32> {
+        self.scalar("_radius")
+    }
+    /// The size of the camera's frustum.
+    pub fn frustum(&self) -> Option<f32> {
+        self.scalar("_frustum")
+    }
+    /// The camera's field of view, in degrees.
+    pub fn fov(&self) -> Option<f32> {
+        self.scalar("_fov")
+    }
+
+    fn scalar(&self, key: &str) -> Option<f32> {
+        self.attributes.get(key).and_then(|value| value.parse().ok())
+    }
+
+    fn vector(&self, key: &str) -> Option<[f32; 3]> {
+        let values = self.attributes.get(key)?
+            .split(' ')
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        if values.len() == 3 {
+            Some([values[0], values[1], values[2]])
+        } else {
+            None
+        }
+    }
+}
+
+named!(pub parse_camera <CompleteByteSlice, Camera>, do_parse!(
+    id: le_u32 >>
+    attributes: parse_dict >>
+    (Camera { id, attributes })
+));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_a_camera_chunk() {
+        let bytes = include_bytes!("resources/valid_camera.bytes").to_vec();
+        let result = parse_camera(CompleteByteSlice(&bytes));
+        match result {
+            Ok((_, camera)) => {
+                assert_eq!(camera.id, 0);
+                assert_eq!(camera.mode(), Some("pers"));
+                assert_eq!(camera.focus(), Some([0.0, 0.0, 12.5]));
+                assert_eq!(camera.angle(), Some([30.0, -45.0, 0.0]));
+                assert_eq!(camera.radius(), Some(64.0));
+                assert_eq!(camera.frustum(), Some(0.414214));
+                assert_eq!(camera.fov(), Some(45.0));
+            }
+            _ => panic!("Expected Done, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn malformed_attributes_are_none() {
+        let mut attributes = Dict::new();
+        attributes.insert("_focus".to_owned(), "1 2".to_owned());
+        attributes.insert("_angle".to_owned(), "1 2 x".to_owned());
+        attributes.insert("_radius".to_owned(), "far".to_owned());
+        let camera = Camera { id: 3, attributes };
+        assert_eq!(camera.mode(), None);
+        assert_eq!(camera.focus(), None);
+        assert_eq!(camera.angle(), None);
+        assert_eq!(camera.radius(), None);
+    }
+}
@@ -0,0 +1,137 @@
+#[cfg(not(feature = "std"))]
+use prelude::*;
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+#[cfg(not(feature = "std"))]
+use std::collections::BTreeMap as Map;
+use {DotVoxData, Model, NodeKind};
+
+/// The content of a model in a form which doesn't depend on the order of its voxels: its size,
+/// then each position it fills with the colour index shown there, sorted by position. Where a
+/// model has several voxels at one position the last is kept, as MagicaVoxel shows the latest.
+fn canonical(model: &Model) -> ([u32; 3], Vec<[u8; 4]>) {
+    let mut filled = model.voxels.iter()
+        .enumerate()
+        .map(|(order, voxel)| ([voxel.x, voxel.y, voxel.z], order, voxel.i))
+        .collect::<Vec<_>>();
+    filled.sort_unstable();
+    let mut voxels: Vec<[u8; 4]> = Vec::with_capacity(filled.len());
+    for ([x, y, z], _, i) in filled {
+        match voxels.last_mut() {
+            Some(last) if last[..3] == [x, y, z] => last[3] = i,
+            _ => voxels.push([x, y, z, i]),
+        }
+    }
+    ([model.size.x, model.size.y, model.size.z], voxels)
+}
+
+/// Removes duplicate models, as described in `DotVoxData::deduplicate_models`.
+pub fn deduplicate_models(data: &mut DotVoxData) -> usize {
+    let mut first_with_content = Map::new();
+    // The new index of every model, which for duplicates is the new index of the first copy
+    let mut new_ids = Vec::with_capacity(data.models.len());
+    let mut is_first = Vec::with_capacity(data.models.len());
+    let mut kept = 0;
+    for model in &data.models {
+        let new_id = *first_with_content.entry(canonical(model)).or_insert(kept);
+        is_first.push(new_id == kept);
+        if new_id == kept {
+            kept += 1;
+        }
+        new_ids.push(new_id);
+    }
+    let removed = data.models.len() - kept;
+    if removed == 0 {
+        return 0;
+    }
+
+    let mut is_first = is_first.into_iter();
+    data.models.retain(|_| is_first.next() == Some(true));
+    // References to models which don't exist are shifted down with the rest, so that they still
+    // don't refer to one
+    let remap = |model_id: usize| new_ids.get(model_id).cloned().unwrap_or_else(|| model_id - removed);
+    for (_, model_id) in &mut data.scene {
+        *model_id = remap(*model_id);
+    }
+    for node in data.scene_graph.nodes_mut() {
+        if let NodeKind::Shape { model_id } = &mut node.kind {
+            *model_id = remap(*model_id as usize) as u32;
+        }
+    }
+    removed
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use scene::test_nodes::shape_node;
+    use {load, SceneGraph, Size, Transform, Voxel};
+
+    fn model(x: u32, voxels: &[(u8, u8)]) -> Model {
+        Model {
+            size: Size { x, y: 1, z: 1 },
+            voxels: voxels.iter().map(|&(x, i)| Voxel { x, y: 0, z: 0, i }).collect(),
+        }
+    }
+
+    fn translated(x: i32) -> Transform {
+        Transform { t: [x, 0, 0], ..Transform::default() }
+    }
+
+    fn data(models: Vec<Model>) -> DotVoxData {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        data.scene = (0..models.len() + 1).map(|id| (translated(id as i32), id)).collect();
+        data.scene_graph = SceneGraph::new();
+        for id in 0..models.len() as u32 + 1 {
+            data.scene_graph.add_node(shape_node(id, id));
+        }
+        data.models = models;
+        data
+    }
+
+    fn shape_models(data: &DotVoxData) -> Vec<u32> {
+        (0..data.scene_graph.len() as u32)
+            .map(|id| match data.scene_graph.get(id).unwrap().kind {
+                NodeKind::Shape { model_id } => model_id,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn duplicates_are_removed_and_references_rewritten() {
+        let mut data = data(vec![
+            model(4, &[(0, 1), (2, 3)]),
+            model(4, &[(1, 1)]),
+            model(4, &[(2, 3), (0, 1)]),
+            model(5, &[(0, 1), (2, 3)]),
+            model(4, &[(1, 1)]),
+        ]);
+        let before: Vec<_> = data.scene.iter().map(|(_, id)| data.models.get(*id).map(canonical)).collect();
+
+        assert_eq!(deduplicate_models(&mut data), 2);
+        assert_eq!(data.models, vec![model(4, &[(0, 1), (2, 3)]), model(4, &[(1, 1)]), model(5, &[(0, 1), (2, 3)])]);
+        let ids: Vec<_> = data.scene.iter().map(|(transform, id)| (transform.t[0], *id)).collect();
+        assert_eq!(ids, vec![(0, 0), (1, 1), (2, 0), (3, 2), (4, 1), (5, 3)]);
+        assert_eq!(shape_models(&data), vec![0, 1, 0, 2, 1, 3]);
+        let after: Vec<_> = data.scene.iter().map(|(_, id)| data.models.get(*id).map(canonical)).collect();
+        assert_eq!(after, before);
+    }
+
+    #[test]
+    fn the_last_voxel_at_a_position_decides_equality() {
+        let mut data = data(vec![model(4, &[(0, 1), (0, 2)]), model(4, &[(0, 2), (0, 1)]), model(4, &[(0, 2)])]);
+        assert_eq!(deduplicate_models(&mut data), 1);
+        assert_eq!(data.scene.iter().map(|(_, id)| *id).collect::<Vec<_>>(), vec![0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn files_without_duplicates_are_untouched() {
+        let mut data = load("src/resources/two-model-scene.vox").unwrap();
+        let scene = data.scene.clone();
+        let scene_graph = data.scene_graph.clone();
+        assert_eq!(deduplicate_models(&mut data), 0);
+        assert_eq!(data.models.len(), 2);
+        assert_eq!((data.scene, data.scene_graph), (scene, scene_graph));
+    }
+}
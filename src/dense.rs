@@ -0,0 +1,156 @@
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use {Model, Size, Voxel};
+
+/// The largest size along each axis of a `DenseModel`, which is also the largest model
+/// MagicaVoxel creates.
+pub const MAX_DENSE_SIZE: u32 = 256;
+
+/// A voxel Model stored as a dense 3D array, for fast lookup of neighbouring voxels
+///
+/// Coordinates are the same as for `Voxel`, so z is up, as in MagicaVoxel. The voxels are
+/// stored with x varying fastest and z slowest, so the value for `(x, y, z)` is at
+/// `x + size.x * (y + size.y * z)`.
+///
+/// Each value is the palette index stored in the .vox file (that is, `Voxel::i + 1`), so that
+/// 0 means there is no voxel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DenseModel {
+    size: Size,
+    voxels: Vec<u8>,
+}
+
+impl DenseModel {
+    /// Creates an empty model of the given size.
+    ///
+    /// Returns `None` if the size exceeds `MAX_DENSE_SIZE` along any axis, as the coordinates of
+    /// a `Voxel` couldn't address the whole model.
+    pub fn new(size: Size) -> Option<DenseModel> {
+        if size.x > MAX_DENSE_SIZE || size.y > MAX_DENSE_SIZE || size.z > MAX_DENSE_SIZE {
+            return None;
+        }
+        let volume = (size.x as usize)
+            .checked_mul(size.y as usize)?
+            .checked_mul(size.z as usize)?;
+        Some(DenseModel { size, voxels: vec![0; volume] })
+    }
+
+    /// The size of the model in voxels
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Every value in the model, in the order described above.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.voxels
+    }
+
+    /// The value at the given position, or `None` if it lies outside the model.
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Option<u8> {
+        self.offset(x, y, z).map(|offset| self.voxels[offset])
+    }
+
+    /// Sets the value at the given position, returning `false` (and leaving the model unchanged)
+    /// if it lies outside the model.
+    pub fn set(&mut self, x: u32, y: u32, z: u32, index: u8) -> bool {
+        match self.offset(x, y, z) {
+            Some(offset) => {
+                self.voxels[offset] = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Converts this back into a `Model`, with a voxel for every non-zero value, ordered as
+    /// described above.
+    pub fn to_sparse(&self) -> Model {
+        let size = self.size;
+        let voxels = self.voxels
+            .iter()
+            .enumerate()
+            .filter(|&(_, &index)| index != 0)
+            .map(|(offset, &index)| {
+                let offset = offset as u32;
+                Voxel {
+                    x: (offset % size.x) as u8,
+                    y: (offset / size.x % size.y) as u8,
+                    z: (offset / size.x / size.y) as u8,
+                    i: index - 1,
+                }
+            })
+            .collect();
+        Model { size, voxels }
+    }
+
+    fn offset(&self, x: u32, y: u32, z: u32) -> Option<usize> {
+        if x < self.size.x && y < self.size.y && z < self.size.z {
+            Some(x as usize + self.size.x as usize * (y as usize + self.size.y as usize * z as usize))
+        } else {
+            None
+        }
+    }
+}
+
+impl Model {
+    /// Converts this into a `DenseModel` of the same size.
+    ///
+    /// Voxels outside the model's size are dropped, and where several voxels share a position
+    /// the last one wins. As values are stored one higher than `Voxel::i`, a voxel with palette
+    /// index 255 is stored as 255 and so reads back as index 254. Returns `None` if the model is
+    /// too large, as described in `DenseModel::new`.
+    pub fn to_dense(&self) -> Option<DenseModel> {
+        let mut dense = DenseModel::new(self.size)?;
+        for voxel in &self.voxels {
+            dense.set(voxel.x as u32, voxel.y as u32, voxel.z as u32, voxel.i.saturating_add(1));
+        }
+        Some(dense)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use load;
+
+    #[test]
+    fn models_survive_a_round_trip() {
+        let model = &load("src/resources/placeholder.vox").unwrap().models[0];
+        let dense = model.to_dense().unwrap();
+        assert_eq!(dense.as_slice(), &[226, 0, 0, 6, 0, 236, 216, 0]);
+        assert_eq!(dense.get(1, 1, 0), Some(6));
+        assert_eq!(dense.get(1, 1, 1), Some(0));
+        let mut sparse = dense.to_sparse();
+        sparse.voxels.sort_by_key(|voxel| (voxel.x, voxel.y, voxel.z));
+        assert_eq!(&sparse, model);
+    }
+
+    #[test]
+    fn accesses_are_bounds_checked() {
+        let mut dense = DenseModel::new(Size { x: 3, y: 2, z: 1 }).unwrap();
+        assert!(dense.set(2, 1, 0, 9));
+        assert_eq!(dense.as_slice()[5], 9);
+        assert!(!dense.set(3, 0, 0, 9));
+        assert!(!dense.set(0, 2, 0, 9));
+        assert!(!dense.set(0, 0, 1, 9));
+        assert_eq!(dense.get(3, 0, 0), None);
+        assert_eq!(dense.get(0, 0, u32::MAX), None);
+    }
+
+    #[test]
+    fn out_of_bounds_voxels_are_dropped() {
+        let model = Model {
+            size: Size { x: 1, y: 1, z: 1 },
+            voxels: vec![Voxel { x: 0, y: 0, z: 0, i: 0 }, Voxel { x: 1, y: 0, z: 0, i: 1 }],
+        };
+        assert_eq!(model.to_dense().unwrap().as_slice(), &[1]);
+    }
+
+    #[test]
+    fn oversized_models_are_rejected() {
+        assert!(DenseModel::new(Size { x: 256, y: 256, z: 256 }).is_some());
+        assert_eq!(DenseModel::new(Size { x: 257, y: 1, z: 1 }), None);
+        let model = Model { size: Size { x: u32::MAX, y: u32::MAX, z: u32::MAX }, voxels: vec![] };
+        assert_eq!(model.to_dense(), None);
+    }
+}
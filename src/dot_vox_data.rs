@@ -1,16 +1,348 @@
-use {Material, Model, Transform};
+#[cfg(not(feature = "std"))]
+use prelude::*;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, BufWriter, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+use bounds::{self, BoundingBox};
+use dedup;
+use flatten::{self, FlattenError};
+use validation::{self, ValidationIssue};
+#[cfg(feature = "std")]
+use writer;
+use {Camera, Color, Dict, RawChunk, Layer, Material, Model, SceneGraph, ShapeInstance, Transform, Voxel, WorldVoxel};
 
 /// Container for .vox file data
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DotVoxData {
     /// The version number of the .vox file.
     pub version: u32,
-    /// A Vec of all the models contained within this file.
+    /// A Vec of all the models contained within this file, in the order they're stored, so that
+    /// a model's index is the ID used to refer to it in the scene graph.
     pub models: Vec<Model>,
-    /// A Vec containing the colour palette as 32-bit integers
+    /// A Vec containing the colour palette as 32-bit integers, in the order stored in the file's
+    /// RGBA chunk, so that `palette[voxel.i]` is the colour of `voxel`. MagicaVoxel may display
+    /// the palette in a different order; see `index_map` and `remapped_palette`.
     pub palette: Vec<u32>,
+    /// The names given to each row of 8 colours in the palette, or an empty Vec if the file
+    /// doesn't name them
+    pub palette_notes: Vec<String>,
+    /// The order in which MagicaVoxel displays the palette, if the file specifies one: the entry
+    /// at each display slot is the (1-based, with 0 standing for 256) palette index shown there
+    #[cfg_attr(feature = "serde", serde(with = "index_map_serde"))]
+    pub index_map: Option<[u8; 256]>,
     /// A Vec containing all the Materials set
     pub materials: Vec<Material>,
-    /// A Vec representing a scene via a list of transformations paired with model indices
+    /// A Vec representing a scene via a list of transformations paired with model indices,
+    /// including models which have been hidden in MagicaVoxel (see `visible_shapes`)
     pub scene: Vec<(Transform, usize)>,
-}
\ No newline at end of file
+    /// The full scene graph, including node attributes such as names, from which `scene` is
+    /// collapsed
+    pub scene_graph: SceneGraph,
+    /// A Vec of all the layers referenced by Transform nodes in the scene graph
+    pub layers: Vec<Layer>,
+    /// A Vec of the render settings (such as lighting, fog and the background colour), each
+    /// identified by its `_type` attribute
+    pub render_objects: Vec<Dict>,
+    /// A Vec of all the cameras saved in the file
+    pub cameras: Vec<Camera>,
+    /// The chunks within the MAIN chunk which this crate doesn't recognise (such as those added
+    /// by other tools) or couldn't parse, in the order they appear in the file. Each is written
+    /// back at its `RawChunk::position` among the other chunks.
+    pub unknown_chunks: Vec<RawChunk>,
+    /// Problems with the models in the file which were worked around while loading it, such as a
+    /// SIZE chunk without an XYZI chunk after it. `validate` reports these along with the
+    /// problems it finds in the data itself.
+    pub model_issues: Vec<ValidationIssue>,
+}
+
+/// Serde only supports arrays of up to 32 elements, so the index map is (de)serialized as a
+/// sequence instead.
+#[cfg(feature = "serde")]
+mod index_map_serde {
+    #[cfg(not(feature = "std"))]
+    use prelude::*;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(index_map: &Option<[u8; 256]>, serializer: S) -> Result<S::Ok, S::Error> {
+        index_map.as_ref().map(|index_map| &index_map[..]).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<[u8; 256]>, D::Error> {
+        match Option::<Vec<u8>>::deserialize(deserializer)? {
+            Some(ref entries) if entries.len() == 256 => {
+                let mut index_map = [0; 256];
+                index_map.copy_from_slice(entries);
+                Ok(Some(index_map))
+            }
+            Some(entries) => Err(D::Error::invalid_length(entries.len(), &"256 palette indices")),
+            None => Ok(None),
+        }
+    }
+}
+
+impl DotVoxData {
+    /// Serializes this data in the .vox format, so that it can be loaded by MagicaVoxel (or by
+    /// `load_bytes`).
+    ///
+    /// Writes the version, every model as a SIZE and XYZI chunk pair, the scene graph nodes
+    /// (ordered by ID) and layers, the palette with its index map and notes, all materials, all
+    /// render settings and all cameras. Each unknown chunk is written exactly as it was read,
+    /// placed at its `RawChunk::position` among these. Palettes are always written with exactly 256 colours, padding with transparent black or
+    /// truncating as needed.
+    ///
+    /// If `scene_graph` is empty, a simple one is written instead so that MagicaVoxel shows the
+    /// models: each entry in `scene` (or each model, if `scene` is empty too) gets its own
+    /// Transform and Shape node within a single group.
+    ///
+    /// # Errors
+    /// Any error returned by the supplied writer, or `InvalidInput` if a voxel's palette index is
+    /// 255 (which can't be stored, as indices are written one higher), if an unknown chunk's ID
+    /// isn't 4 bytes long, or if a scene graph is written from a `scene` entry whose rotation
+    /// can't be stored in a .vox file.
+    #[cfg(feature = "std")]
+    pub fn write_vox<W: Write>(&self, writer: W) -> io::Result<()> {
+        writer::write_vox(self, writer)
+    }
+
+    /// Writes this data to a file at the given path, as with `write_vox`, replacing the file if
+    /// it already exists.
+    ///
+    /// # Errors
+    /// If the file can't be created or written, or as with `write_vox`.
+    #[cfg(feature = "std")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_vox(&mut writer)?;
+        writer.flush()
+    }
+
+    /// The name of the palette row containing the colour at `index` in `palette` (and so the
+    /// colour of any voxel with `Voxel.i == index`). Rows are counted in display order, so
+    /// `index_map` is taken into account if present.
+    ///
+    /// Returns `None` if the file doesn't name that row, or names it with an empty string.
+    pub fn color_name(&self, index: u8) -> Option<&str> {
+        let slot = self.index_map
+            .and_then(|map| map.iter().position(|&entry| entry.wrapping_sub(1) == index))
+            .unwrap_or(index as usize);
+        self.palette_notes
+            .get(slot / 8)
+            .map(String::as_str)
+            .filter(|name| !name.is_empty())
+    }
+
+    /// The colour at `index` in `palette` (and so the colour of any voxel with
+    /// `Voxel.i == index`), or transparent black if the palette has no such colour.
+    pub fn color(&self, index: u8) -> Color {
+        self.palette.get(index as usize).cloned().map(Color::from).unwrap_or_default()
+    }
+
+    /// The palette as 256 colours of 4 bytes each, in the order red, green, blue and alpha,
+    /// ready to be uploaded as a 256x1 RGBA8 texture. Colours missing from `palette` are
+    /// transparent black.
+    pub fn palette_as_rgba_bytes(&self) -> [u8; 1024] {
+        let mut bytes = [0; 1024];
+        for (colour, bytes) in self.palette.iter().zip(bytes.chunks_mut(4)) {
+            bytes.copy_from_slice(&Color::from(*colour).to_rgba());
+        }
+        bytes
+    }
+
+    /// The palette in the order MagicaVoxel displays it, applying `index_map` if present.
+    ///
+    /// Without an index map this is the same as `palette`. With one, exactly 256 colours are
+    /// returned, with any colours missing from `palette` being transparent black.
+    pub fn remapped_palette(&self) -> Vec<u32> {
+        match self.index_map {
+            Some(_) => (0..=255).map(|slot| self.effective_color(slot)).collect(),
+            None => self.palette.clone(),
+        }
+    }
+
+    /// The colour MagicaVoxel displays at `index` in its palette, applying `index_map` if
+    /// present. Transparent black is returned if `palette` has no colour for that index.
+    ///
+    /// Note that voxel indices are never remapped: the colour of a voxel is always
+    /// `palette[voxel.i]`.
+    pub fn effective_color(&self, index: u8) -> u32 {
+        let palette_index = match self.index_map {
+            Some(map) => map[index as usize].wrapping_sub(1),
+            None => index,
+        };
+        self.palette.get(palette_index as usize).cloned().unwrap_or(0)
+    }
+
+    /// The indices within `models` of every model with at least one voxel of the given colour
+    /// index, as stored in `Voxel::i`, in ascending order.
+    pub fn models_using_index(&self, i: u8) -> Vec<usize> {
+        self.models.iter()
+            .enumerate()
+            .filter(|(_, model)| model.voxels_with_index(i).next().is_some())
+            .map(|(model_id, _)| model_id)
+            .collect()
+    }
+
+    /// An iterator over every voxel whose material matches `predicate`, paired with the index
+    /// of its model within `models`. Models are visited in order, and the voxels of each in the
+    /// order they're stored.
+    ///
+    /// A material applies to the voxels of the palette colour it's stored alongside, so material
+    /// `id` applies to voxels with `Voxel::i == id - 1` (as both `Material::id` and the file's
+    /// colour indices start at 1). Voxels with no material in `materials` never match, and
+    /// neither do materials with an ID of 0 or above 255.
+    pub fn voxels_with_material<P: Fn(&Material) -> bool>(&self, predicate: P)
+                                                          -> impl Iterator<Item = (usize, &Voxel)> {
+        let mut matches = [false; 256];
+        for material in &self.materials {
+            if (1..=255).contains(&material.id) && predicate(material) {
+                matches[material.id as usize - 1] = true;
+            }
+        }
+        self.models.iter().enumerate().flat_map(move |(model_id, model)| {
+            model.voxels.iter()
+                .filter(move |voxel| matches[voxel.i as usize])
+                .map(move |voxel| (model_id, voxel))
+        })
+    }
+
+    /// The box containing every voxel in the scene, in world coordinates, or `None` if the scene
+    /// has no voxels.
+    ///
+    /// Each model's `Model::bounding_box` is positioned in the world by its transformation, as in
+    /// `world_voxels`, so this matches the voxels `world_voxels` yields without visiting them
+    /// all. Scene entries referring to models which don't exist are skipped.
+    pub fn scene_bounding_box(&self) -> Option<BoundingBox> {
+        bounds::scene_bounding_box(self)
+    }
+
+    /// Removes models which are identical to an earlier model, returning how many were removed.
+    ///
+    /// Models are identical if they have the same size and show the same colour at every
+    /// position, regardless of the order of their voxels. The first copy of each model is kept,
+    /// and every entry in `scene` and every Shape node which referred to a copy is changed to
+    /// refer to it, so that every reference still shows the same voxels. References to models
+    /// which don't exist are shifted down along with the others so that they still don't refer
+    /// to one.
+    pub fn deduplicate_models(&mut self) -> usize {
+        dedup::deduplicate_models(self)
+    }
+
+    /// Merges every model in the scene into a single model, positioning their voxels as in
+    /// `world_voxels` and then shifting them so that the smallest coordinates are 0.
+    ///
+    /// Where voxels from several models overlap, the one from the model latest in `scene` wins.
+    /// The result's voxels are ordered as described in `DenseModel`, which can't store palette
+    /// index 255, so voxels with that index are given index 254 instead (as `Model::to_dense`
+    /// does). To merge scenes which are too large for a single model, use `world_voxels`
+    /// directly.
+    ///
+    /// # Errors
+    /// If `scene` refers to a model which doesn't exist, contains no voxels, or doesn't fit in a
+    /// model of `MAX_DENSE_SIZE` along every axis.
+    pub fn flatten_to_model(&self) -> Result<Model, FlattenError> {
+        flatten::flatten(self)
+    }
+
+    /// Checks this data for problems which the parser lets through, to be reported to the user
+    /// or fixed up before the data is used.
+    ///
+    /// This checks that every voxel lies within its model's size and has a colour in the
+    /// palette, that every entry in `scene` and every Shape node refers to an existing model,
+    /// that every child of a Group or Transform node exists, and that the scene graph has a root,
+    /// no cycles and no Shape nodes without a parent. Problems with the file's models which were
+    /// worked around while loading it (see `model_issues`) come first. An empty Vec means no
+    /// problems were found, so an empty `scene` really is an empty scene rather than a broken
+    /// scene graph.
+    ///
+    /// Note that the file's colour index 0 can't be detected here, as the parser stores it the
+    /// same way as index 1.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        validation::validate(self)
+    }
+
+    /// The layer with the given ID, as referenced by `NodeKind::Transform::layer_id` and
+    /// `ShapeInstance::layer_id`, or `None` if the file has no such layer.
+    pub fn layer(&self, id: u32) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.id == id)
+    }
+
+    /// An iterator over the shapes in the scene graph which are visible in MagicaVoxel, as in
+    /// `SceneGraph::iter_shapes`.
+    ///
+    /// A shape is hidden if any node on its path is hidden (see `ShapeInstance::hidden`), or if
+    /// the layer it belongs to is hidden. Shapes on layers which don't exist are visible.
+    pub fn visible_shapes(&self) -> impl Iterator<Item = ShapeInstance<'_>> {
+        self.scene_graph.iter_shapes().filter(move |shape| {
+            !shape.hidden && !shape.layer_id.and_then(|id| self.layer(id)).is_some_and(Layer::hidden)
+        })
+    }
+
+    /// An iterator over the voxels of every model in the scene, positioned in the world as
+    /// described in `Model::world_voxels`.
+    ///
+    /// Models appear in the same order as in `scene`, and a model appearing several times in the
+    /// scene has its voxels yielded each time. Scene entries referring to models which don't
+    /// exist are skipped.
+    pub fn world_voxels(&self) -> impl Iterator<Item = WorldVoxel> + '_ {
+        self.scene.iter().flat_map(move |(transform, model_id)| {
+            self.models.get(*model_id)
+                .into_iter()
+                .flat_map(move |model| model.world_voxels(transform))
+        })
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use bincode;
+    use serde_json;
+    use load;
+
+    const FILES: &[&str] = &[
+        "placeholder-with-materials.vox",
+        "two-model-scene.vox",
+        "three-layers.vox",
+        "cameras.vox",
+        "index-map.vox",
+        "palette-notes.vox",
+    ];
+
+    #[test]
+    fn data_survives_a_json_round_trip() {
+        for file in FILES {
+            let data = load(&format!("src/resources/{}", file)).unwrap();
+            let json = serde_json::to_string(&data).unwrap();
+            assert_eq!(serde_json::from_str::<super::DotVoxData>(&json).unwrap(), data, "{}", file);
+        }
+    }
+
+    #[test]
+    fn data_survives_a_bincode_round_trip() {
+        for file in FILES {
+            let data = load(&format!("src/resources/{}", file)).unwrap();
+            let bytes = bincode::serialize(&data).unwrap();
+            assert_eq!(bincode::deserialize::<super::DotVoxData>(&bytes).unwrap(), data, "{}", file);
+        }
+    }
+
+    #[test]
+    fn dictionaries_keep_string_keys() {
+        let data = load("src/resources/placeholder-with-materials.vox").unwrap();
+        let json = serde_json::to_value(&data.materials[216]).unwrap();
+        assert_eq!(json["properties"]["_type"], "_metal");
+        assert_eq!(json["id"], 216);
+    }
+
+    #[test]
+    fn truncated_index_maps_are_rejected() {
+        let data = load("src/resources/index-map.vox").unwrap();
+        let mut json = serde_json::to_value(&data).unwrap();
+        json["index_map"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<super::DotVoxData>(json).is_err());
+    }
+}
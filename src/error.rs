@@ -0,0 +1,82 @@
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use std::error::Error;
+use std::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+/// The reason a .vox file couldn't be parsed, and where
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// The ID of the chunk being parsed when the error occurred, if any.
+    pub chunk_id: Option<String>,
+    /// The offset of the error from the start of the input, in bytes. Within a chunk, this is
+    /// the offset of the start of the chunk.
+    pub offset: usize,
+    /// A description of what was expected.
+    pub message: String,
+}
+
+impl ParseError {
+    pub(crate) fn new<S: Into<String>>(chunk_id: Option<&str>, offset: usize, message: S) -> ParseError {
+        ParseError {
+            chunk_id: chunk_id.map(str::to_owned),
+            offset,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.chunk_id {
+            Some(ref id) => write!(f, "{} chunk at offset {:#X}: {}", id, self.offset, self.message),
+            None => write!(f, "At offset {:#X}: {}", self.offset, self.message),
+        }
+    }
+}
+impl Error for ParseError {}
+
+/// The reason a .vox file couldn't be loaded from a file or reader
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file couldn't be opened or read.
+    Io(io::Error),
+    /// The contents of the file couldn't be parsed.
+    Parse(ParseError),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "Unable to read file: {}", error),
+            LoadError::Parse(error) => error.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            LoadError::Parse(error) => Some(error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<ParseError> for LoadError {
+    fn from(error: ParseError) -> LoadError {
+        LoadError::Parse(error)
+    }
+}
@@ -0,0 +1,105 @@
+use std::error::Error;
+use std::fmt;
+use {DenseModel, DotVoxData, Model, Size, WorldVoxel, MAX_DENSE_SIZE};
+
+/// The reasons a scene can't be flattened into a single model by `DotVoxData::flatten_to_model`
+#[derive(Clone, Debug, PartialEq)]
+pub enum FlattenError {
+    /// The scene contains no voxels.
+    Empty,
+    /// An entry in `DotVoxData::scene` refers to a model which doesn't exist.
+    MissingModel {
+        /// The index of the entry in `DotVoxData::scene`.
+        entry: usize,
+        /// The model ID of the entry.
+        model_id: usize,
+    },
+    /// The scene is larger than `MAX_DENSE_SIZE` along at least one axis.
+    TooLarge {
+        /// The size of the scene along each axis.
+        extent: [u64; 3],
+    },
+}
+
+impl fmt::Display for FlattenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlattenError::Empty => write!(f, "The scene contains no voxels"),
+            FlattenError::MissingModel { entry, model_id } =>
+                write!(f, "Scene entry {} refers to a model which doesn't exist (id: {})", entry, model_id),
+            FlattenError::TooLarge { extent } =>
+                write!(f, "The scene is too large to fit in a single model ({}x{}x{})",
+                       extent[0], extent[1], extent[2]),
+        }
+    }
+}
+impl Error for FlattenError {}
+
+/// Merges every model in the scene into one, as described in `DotVoxData::flatten_to_model`.
+pub fn flatten(data: &DotVoxData) -> Result<Model, FlattenError> {
+    if let Some(entry) = data.scene.iter().position(|&(_, model_id)| model_id >= data.models.len()) {
+        return Err(FlattenError::MissingModel { entry, model_id: data.scene[entry].1 });
+    }
+
+    let bounds = data.scene_bounding_box().ok_or(FlattenError::Empty)?;
+    let (min, extent) = (bounds.min, bounds.size());
+    if extent.iter().any(|&extent| extent > MAX_DENSE_SIZE as u64) {
+        return Err(FlattenError::TooLarge { extent });
+    }
+
+    let size = Size { x: extent[0] as u32, y: extent[1] as u32, z: extent[2] as u32 };
+    let mut dense = DenseModel::new(size).expect("extent was checked against MAX_DENSE_SIZE");
+    for WorldVoxel { x, y, z, i } in data.world_voxels() {
+        // Index 255 can't be stored one higher, so it becomes 254 as documented
+        dense.set((x - min[0]) as u32, (y - min[1]) as u32, (z - min[2]) as u32, i.saturating_add(1));
+    }
+    Ok(dense.to_sparse())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use {load, Transform, Voxel};
+
+    #[test]
+    fn scenes_are_merged_into_one_model() {
+        let data = load("src/resources/two-model-scene.vox").unwrap();
+        let model = flatten(&data).unwrap();
+        assert_eq!(model.size, Size { x: 6, y: 3, z: 3 });
+        assert_eq!(model.voxels.len(), 54);
+        let odd: Vec<_> = model.voxels.iter().filter(|voxel| voxel.i != 78 && voxel.i != 212).collect();
+        assert_eq!(odd, vec![&Voxel { x: 5, y: 0, z: 0, i: 39 }, &Voxel { x: 0, y: 2, z: 0, i: 215 }]);
+    }
+
+    #[test]
+    fn later_models_overwrite_earlier_ones() {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        data.models.push(Model {
+            size: Size { x: 1, y: 1, z: 1 },
+            voxels: vec![Voxel { x: 0, y: 0, z: 0, i: 9 }, Voxel { x: 0, y: 0, z: 0, i: 10 }],
+        });
+        // Place the new model on the placeholder's voxel at (1, 1, 0), which is centred on the
+        // origin
+        data.scene.push((Transform { t: [0, 0, 0], ..data.scene[0].0 }, 1));
+        let model = flatten(&data).unwrap();
+        assert_eq!(model.voxels.len(), 4);
+        assert!(model.voxels.contains(&Voxel { x: 1, y: 1, z: 0, i: 10 }));
+    }
+
+    #[test]
+    fn oversized_scenes_are_rejected() {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        let (transform, model_id) = data.scene[0];
+        data.scene.push((Transform { t: [300, 0, 1], ..transform }, model_id));
+        assert_eq!(flatten(&data), Err(FlattenError::TooLarge { extent: [302, 2, 2] }));
+    }
+
+    #[test]
+    fn missing_models_and_empty_scenes_are_rejected() {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        data.scene[0].1 = 2;
+        assert_eq!(flatten(&data), Err(FlattenError::MissingModel { entry: 0, model_id: 2 }));
+        data.scene.clear();
+        assert_eq!(flatten(&data), Err(FlattenError::Empty));
+    }
+}
@@ -0,0 +1,300 @@
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+use error::{LoadError, ParseError};
+use std::io::{self, Read};
+use parser::MAGIC_NUMBER;
+use Size;
+
+const CHUNK_HEADER_SIZE: u64 = 12;
+const SIZE_CONTENT_SIZE: u64 = 12;
+
+/// The version and model sizes of a .vox file, as read by `load_header`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct VoxHeader {
+    /// The version number of the .vox file.
+    pub version: u32,
+    /// The size of each model in the file, in the same order as `DotVoxData::models`.
+    pub model_sizes: Vec<Size>,
+}
+
+/// A reader which counts the bytes read through it, so that errors can give their offset
+struct CountingReader<R> {
+    reader: R,
+    offset: usize,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.reader.read(buf)?;
+        self.offset += count;
+        Ok(count)
+    }
+}
+
+/// The header of a chunk, and the offset of the start of the chunk
+struct ChunkHeader {
+    id: [u8; 4],
+    offset: usize,
+    content_size: u64,
+    children_size: u64,
+}
+
+impl ChunkHeader {
+    fn error<S: Into<String>>(&self, message: S) -> LoadError {
+        let id = String::from_utf8_lossy(&self.id);
+        ParseError::new(Some(&id), self.offset, message).into()
+    }
+}
+
+/// Reads the header of a .vox file and the SIZE chunk of every model, skipping over everything
+/// else (such as the voxels themselves) without storing it.
+pub fn read_header<R: Read>(reader: R) -> Result<VoxHeader, LoadError> {
+    let mut reader = CountingReader { reader, offset: 0 };
+    let expected_magic_number = format!("the magic number {:?}", MAGIC_NUMBER);
+    let mut magic_number = [0; 4];
+    reader.read_exact(&mut magic_number).map_err(|error| eof_error(error, 0, &expected_magic_number))?;
+    if magic_number != MAGIC_NUMBER.as_bytes() {
+        return Err(ParseError::new(None, 0, format!("expected {}", expected_magic_number)).into());
+    }
+    let version = reader.read_u32::<LittleEndian>().map_err(|error| eof_error(error, 4, "a version number"))?;
+    let mut header = VoxHeader { version, model_sizes: vec![] };
+    let main = read_chunk_header(&mut reader)?;
+    if &main.id != b"MAIN" {
+        return Ok(header);
+    }
+    skip(&mut reader, &main, main.content_size)?;
+    read_children(&mut reader, &main, &mut header.model_sizes)?;
+    Ok(header)
+}
+
+/// Reads the children of `parent` (the MAIN chunk), collecting the SIZE chunk of each model. As
+/// when loading, a model is a SIZE chunk immediately followed by an XYZI chunk, a SIZE or XYZI
+/// chunk without the other stands for an empty model (with a size of zero if the SIZE chunk is
+/// missing), and chunks nested within the children are skipped over whole.
+fn read_children<R: Read>(reader: &mut CountingReader<R>, parent: &ChunkHeader, sizes: &mut Vec<Size>)
+                          -> Result<(), LoadError> {
+    let is_model_chunk = |chunk: &ChunkHeader, id: &[u8; 4]| &chunk.id == id && chunk.children_size == 0;
+    let mut remaining = parent.children_size;
+    // Whether the previous chunk was the SIZE chunk of a model
+    let mut after_size = false;
+    while remaining > 0 {
+        if remaining < CHUNK_HEADER_SIZE {
+            let message = format!("expected a chunk header, but only {} bytes remain in the {} chunk",
+                                  remaining, String::from_utf8_lossy(&parent.id));
+            return Err(ParseError::new(None, reader.offset, message).into());
+        }
+        let chunk = read_chunk_header(reader)?;
+        let chunk_size = CHUNK_HEADER_SIZE + chunk.content_size + chunk.children_size;
+        if chunk_size > remaining {
+            return Err(chunk.error(format!("declared {} bytes of content and children, but only {} remain in the {} chunk",
+                                           chunk_size - CHUNK_HEADER_SIZE,
+                                           remaining - CHUNK_HEADER_SIZE,
+                                           String::from_utf8_lossy(&parent.id))));
+        }
+        remaining -= chunk_size;
+
+        if is_model_chunk(&chunk, b"XYZI") && !after_size {
+            sizes.push(Size { x: 0, y: 0, z: 0 });
+        }
+        after_size = is_model_chunk(&chunk, b"SIZE") && chunk.content_size >= SIZE_CONTENT_SIZE;
+        if after_size {
+            sizes.push(read_size(reader).map_err(|error| match error.kind() {
+                io::ErrorKind::UnexpectedEof => chunk.error("expected 12 bytes of content, but the input ended"),
+                _ => error.into(),
+            })?);
+            skip(reader, &chunk, chunk.content_size - SIZE_CONTENT_SIZE)?;
+        } else {
+            skip(reader, &chunk, chunk.content_size)?;
+        }
+        skip_children(reader, &chunk)?;
+    }
+    Ok(())
+}
+
+fn read_chunk_header<R: Read>(reader: &mut CountingReader<R>) -> Result<ChunkHeader, LoadError> {
+    let offset = reader.offset;
+    let mut header = [0; CHUNK_HEADER_SIZE as usize];
+    reader.read_exact(&mut header).map_err(|error| eof_error(error, offset, "a chunk header"))?;
+    let mut id = [0; 4];
+    id.copy_from_slice(&header[..4]);
+    Ok(ChunkHeader {
+        id,
+        offset,
+        content_size: LittleEndian::read_u32(&header[4..8]) as u64,
+        children_size: LittleEndian::read_u32(&header[8..]) as u64,
+    })
+}
+
+fn read_size<R: Read>(reader: &mut R) -> io::Result<Size> {
+    Ok(Size {
+        x: reader.read_u32::<LittleEndian>()?,
+        y: reader.read_u32::<LittleEndian>()?,
+        z: reader.read_u32::<LittleEndian>()?,
+    })
+}
+
+/// Skips `count` bytes of `chunk`'s content.
+fn skip<R: Read>(reader: &mut R, chunk: &ChunkHeader, count: u64) -> Result<(), LoadError> {
+    let skipped = io::copy(&mut reader.by_ref().take(count), &mut io::sink())?;
+    if skipped == count {
+        Ok(())
+    } else {
+        Err(chunk.error(format!("declared {} bytes of content, but the input ended", chunk.content_size)))
+    }
+}
+
+/// Skips the children of `chunk`.
+fn skip_children<R: Read>(reader: &mut R, chunk: &ChunkHeader) -> Result<(), LoadError> {
+    let skipped = io::copy(&mut reader.by_ref().take(chunk.children_size), &mut io::sink())?;
+    if skipped == chunk.children_size {
+        Ok(())
+    } else {
+        Err(chunk.error(format!("declared {} bytes of children, but the input ended", chunk.children_size)))
+    }
+}
+
+/// Reports I/O errors caused by the input ending early as parse errors.
+fn eof_error(error: io::Error, offset: usize, expected: &str) -> LoadError {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => {
+            ParseError::new(None, offset, format!("expected {}, but the input ended", expected)).into()
+        }
+        _ => error.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    fn chunk(id: &[u8], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.write_u32::<LittleEndian>(content.len() as u32).unwrap();
+        bytes.write_u32::<LittleEndian>(children.len() as u32).unwrap();
+        bytes.extend_from_slice(content);
+        bytes.extend_from_slice(children);
+        bytes
+    }
+
+    fn size(x: u32, y: u32, z: u32) -> Vec<u8> {
+        let mut content = vec![];
+        for dimension in &[x, y, z] {
+            content.write_u32::<LittleEndian>(*dimension).unwrap();
+        }
+        chunk(b"SIZE", &content, &[])
+    }
+
+    fn vox_file(children: &[u8]) -> Vec<u8> {
+        let mut bytes = b"VOX ".to_vec();
+        bytes.write_u32::<LittleEndian>(150).unwrap();
+        bytes.extend(chunk(b"MAIN", &[], children));
+        bytes
+    }
+
+    #[test]
+    fn can_read_the_header_of_a_vox_file() {
+        let bytes = include_bytes!("resources/two-model-scene.vox");
+        let header = read_header(&bytes[..]).unwrap();
+        assert_eq!(header.version, 150);
+        let data = ::load_bytes(bytes).unwrap();
+        let sizes: Vec<Size> = data.models.iter().map(|model| model.size).collect();
+        assert_eq!(header.model_sizes, sizes);
+    }
+
+    #[test]
+    fn headers_match_the_models_of_every_resource() {
+        let mut checked = 0;
+        for entry in ::std::fs::read_dir("src/resources").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() != Some("vox".as_ref()) {
+                continue;
+            }
+            let data = match ::load(path.to_str().unwrap()) {
+                Ok(data) => data,
+                Err(_) => {
+                    assert!(read_header(::std::fs::File::open(&path).unwrap()).is_err(), "{:?}", path);
+                    continue;
+                }
+            };
+            let header = read_header(::std::fs::File::open(&path).unwrap()).unwrap();
+            let sizes: Vec<Size> = data.models.iter().map(|model| model.size).collect();
+            assert_eq!(header.version, data.version, "{:?}", path);
+            assert_eq!(header.model_sizes, sizes, "{:?}", path);
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn sizes_of_incomplete_and_nested_models_match_loading() {
+        let mut children = size(1, 2, 3);
+        children.extend(chunk(b"XYZI", &[1, 0, 0, 0, 0, 0, 0, 1], &[]));
+        let mut nested = size(4, 5, 6);
+        nested.extend(chunk(b"XYZI", &[0, 0, 0, 0], &[]));
+        children.extend(chunk(b"nGRP", &[], &nested));
+        children.extend(size(7, 8, 9));
+        children.extend(chunk(b"nTRN", &[], &[]));
+        children.extend(chunk(b"XYZI", &[0, 0, 0, 0], &[]));
+        children.extend(size(10, 11, 12));
+        let bytes = vox_file(&children);
+        let header = read_header(&bytes[..]).unwrap();
+        assert_eq!(header.model_sizes, vec![
+            Size { x: 1, y: 2, z: 3 },
+            Size { x: 7, y: 8, z: 9 },
+            Size { x: 0, y: 0, z: 0 },
+            Size { x: 10, y: 11, z: 12 },
+        ]);
+        let sizes: Vec<Size> = ::load_bytes(&bytes).unwrap().models.iter().map(|model| model.size).collect();
+        assert_eq!(header.model_sizes, sizes);
+    }
+
+    #[test]
+    fn truncated_files_cause_errors() {
+        let mut children = size(1, 2, 3);
+        children.extend(chunk(b"XYZI", &[1, 0, 0, 0, 0, 0, 0, 1], &[]));
+        let bytes = vox_file(&children);
+        for length in 0..bytes.len() {
+            assert!(read_header(&bytes[..length]).is_err(), "Read {} bytes", length);
+        }
+        assert!(read_header(&bytes[..]).is_ok());
+    }
+
+    #[test]
+    fn chunks_overrunning_their_parent_cause_errors() {
+        let mut children = chunk(b"PACK", &[], &size(4, 5, 6));
+        // Claim the PACK chunk's children are longer than the rest of the MAIN chunk
+        children[8] = 32;
+        match read_header(&vox_file(&children)[..]) {
+            Err(LoadError::Parse(error)) => {
+                assert_eq!(error.chunk_id, Some("PACK".to_owned()));
+                assert_eq!(error.offset, 20);
+                assert_eq!(error.message, "declared 32 bytes of content and children, but only 24 remain in the MAIN chunk");
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nested_chunks_are_skipped_without_being_read() {
+        // Chunks within the PACK chunk's children aren't read, just as when loading
+        let children = chunk(b"PACK", &[], &[0xff; 7]);
+        let bytes = vox_file(&children);
+        assert!(::load_bytes(&bytes).is_ok());
+        assert_eq!(read_header(&bytes[..]).unwrap().model_sizes, vec![]);
+    }
+
+    #[test]
+    fn reader_errors_are_passed_on() {
+        struct FailingReader;
+        impl Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::ConnectionReset, "disconnected"))
+            }
+        }
+        match read_header(FailingReader) {
+            Err(LoadError::Io(error)) => assert_eq!(error.kind(), io::ErrorKind::ConnectionReset),
+            other => panic!("Expected an I/O error, got {:?}", other),
+        }
+    }
+}
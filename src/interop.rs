@@ -0,0 +1,162 @@
+//! Conversions of `Transform` into the matrix types of other maths crates, as described in
+//! `Transform`'s documentation.
+
+#[cfg(feature = "glam")]
+use glam::{Affine3A, Mat3, Mat4, Vec3};
+use Transform;
+
+impl Transform {
+    /// The transformation as a row-major 4x4 matrix.
+    fn to_rows(self) -> [[f32; 4]; 4] {
+        let mut rows = [[0.0, 0.0, 0.0, 1.0]; 4];
+        for (row, (r, t)) in rows.iter_mut().zip(self.r.iter().zip(&self.t)) {
+            *row = [r[0] as f32, r[1] as f32, r[2] as f32, *t as f32];
+        }
+        rows
+    }
+
+    /// The transformation as a column-major 4x4 matrix.
+    fn to_columns(self) -> [[f32; 4]; 4] {
+        let rows = self.to_rows();
+        let mut columns = [[0.0; 4]; 4];
+        for (i, column) in columns.iter_mut().enumerate() {
+            for (j, entry) in column.iter_mut().enumerate() {
+                *entry = rows[j][i];
+            }
+        }
+        columns
+    }
+
+    /// The transformation as a `glam` matrix, as described in `Transform`.
+    ///
+    /// Translations too large to be represented exactly by an `f32` are rounded.
+    #[cfg(feature = "glam")]
+    pub fn to_mat4(&self) -> Mat4 {
+        Mat4::from_cols_array_2d(&self.to_columns())
+    }
+
+    /// The transformation as a `glam` affine transformation, as described in `to_mat4`.
+    #[cfg(feature = "glam")]
+    pub fn to_affine3a(&self) -> Affine3A {
+        let columns = self.to_columns();
+        let column = |i: usize| Vec3::new(columns[i][0], columns[i][1], columns[i][2]);
+        Affine3A::from_mat3_translation(Mat3::from_cols(column(0), column(1), column(2)), column(3))
+    }
+}
+
+/// Converts the transformation into a matrix with the translation in its last column, as
+/// described in `Transform`.
+#[cfg(feature = "mint")]
+impl From<Transform> for mint::ColumnMatrix4<f32> {
+    fn from(transform: Transform) -> Self {
+        transform.to_columns().into()
+    }
+}
+
+/// Converts the transformation into a matrix with the translation in its last column, as
+/// described in `Transform`.
+#[cfg(feature = "mint")]
+impl From<Transform> for mint::RowMatrix4<f32> {
+    fn from(transform: Transform) -> Self {
+        transform.to_rows().into()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    #[cfg(feature = "glam")]
+    use {load, Model, NodeKind, Size, Voxel};
+    use super::*;
+
+    fn rotate_z() -> Transform {
+        Transform { t: [10, 20, 30], r: [[0, -1, 0], [1, 0, 0], [0, 0, 1]] }
+    }
+
+    #[test]
+    fn rows_and_columns_are_transposes() {
+        assert_eq!(rotate_z().to_rows(), [
+            [0.0, -1.0, 0.0, 10.0],
+            [1.0, 0.0, 0.0, 20.0],
+            [0.0, 0.0, 1.0, 30.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert_eq!(rotate_z().to_columns(), [
+            [0.0, 1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [10.0, 20.0, 30.0, 1.0],
+        ]);
+    }
+
+    #[cfg(feature = "mint")]
+    #[test]
+    fn mint_matrices_keep_the_translation_in_the_last_column() {
+        let columns = mint::ColumnMatrix4::from(rotate_z());
+        assert_eq!(columns.w, mint::Vector4 { x: 10.0, y: 20.0, z: 30.0, w: 1.0 });
+        assert_eq!(columns.x, mint::Vector4 { x: 0.0, y: 1.0, z: 0.0, w: 0.0 });
+        let rows = mint::RowMatrix4::from(rotate_z());
+        assert_eq!(rows.x, mint::Vector4 { x: 0.0, y: -1.0, z: 0.0, w: 10.0 });
+        assert_eq!(rows.w, mint::Vector4 { x: 0.0, y: 0.0, z: 0.0, w: 1.0 });
+    }
+
+    #[cfg(feature = "glam")]
+    #[test]
+    fn glam_matrices_transform_points_like_transforms() {
+        let flip_x = Transform { t: [0, 0, 10], r: [[-1, 0, 0], [0, 1, 0], [0, 0, 1]] };
+        let p = [4, 5, 6];
+        for transform in &[rotate_z(), flip_x, rotate_z().apply(flip_x), flip_x.inverse()] {
+            let [x, y, z] = transform.transform_point(p);
+            let expected = Vec3::new(x as f32, y as f32, z as f32);
+            let point = Vec3::new(p[0] as f32, p[1] as f32, p[2] as f32);
+            assert_eq!(transform.to_mat4().transform_point3(point), expected);
+            assert_eq!(transform.to_affine3a().transform_point3(point), expected);
+        }
+        assert_eq!(rotate_z().apply(flip_x).to_mat4(), flip_x.to_mat4() * rotate_z().to_mat4());
+    }
+
+    #[cfg(feature = "glam")]
+    #[test]
+    fn world_matrices_multiply_from_the_root_down() {
+        let data = load("src/resources/two-model-scene.vox").unwrap();
+        for shape in data.scene_graph.iter_shapes() {
+            let world = shape.path.iter()
+                .filter_map(|id| data.scene_graph.get(*id).and_then(|node| node.kind.transform()))
+                .fold(Mat4::IDENTITY, |world, transform| world * transform.to_mat4());
+            assert_eq!(world, shape.transform.to_mat4());
+        }
+        assert!(data.scene_graph.nodes().any(|node| match node.kind {
+            NodeKind::Transform { .. } => node.kind.transform() != Some(Transform::default()),
+            _ => false,
+        }));
+    }
+
+    #[cfg(feature = "glam")]
+    fn assert_centring_matches(model: &Model, transform: &Transform) {
+        let size = Vec3::new(model.size.x as f32, model.size.y as f32, model.size.z as f32);
+        let extents = transform.to_mat4().transform_vector3(size).abs();
+        let odd = Vec3::new(extents.x % 2.0, extents.y % 2.0, extents.z % 2.0) / 2.0;
+        for (voxel, world) in model.voxels.iter().zip(model.world_voxels(transform)) {
+            let centre = Vec3::new(voxel.x as f32, voxel.y as f32, voxel.z as f32) + 0.5;
+            let placed = transform.to_mat4().transform_point3(centre - size / 2.0) + odd;
+            assert_eq!(placed, Vec3::new(world.x as f32, world.y as f32, world.z as f32) + 0.5);
+        }
+    }
+
+    #[cfg(feature = "glam")]
+    #[test]
+    fn centring_matches_the_world_voxels() {
+        let data = load("src/resources/two-model-scene.vox").unwrap();
+        for (transform, model_id) in &data.scene {
+            assert_centring_matches(&data.models[*model_id], transform);
+        }
+
+        let model = Model {
+            size: Size { x: 2, y: 3, z: 4 },
+            voxels: vec![Voxel { x: 0, y: 0, z: 0, i: 1 }, Voxel { x: 1, y: 2, z: 3, i: 2 }],
+        };
+        for r in &[[[1, 0, 0], [0, 1, 0], [0, 0, 1]], [[-1, 0, 0], [0, 1, 0], [0, 0, 1]],
+                   [[0, -1, 0], [1, 0, 0], [0, 0, 1]], [[0, 0, 1], [-1, 0, 0], [0, -1, 0]]] {
+            assert_centring_matches(&model, &Transform { t: [10, 20, 30], r: *r });
+        }
+    }
+}
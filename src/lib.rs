@@ -1,40 +1,121 @@
 //! Load MagicaVoxel .vox files into Rust
+//!
+//! Without the default `std` feature the crate only needs `alloc`, and everything other than
+//! reading from files and readers and writing .vox files is still available.
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
 extern crate byteorder;
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 extern crate env_logger;
+#[cfg(all(test, feature = "std"))]
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
 #[macro_use]
 extern crate nom;
+#[cfg(feature = "glam")]
+extern crate glam;
+#[cfg(feature = "mint")]
+extern crate mint;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 
 #[cfg(test)]
 extern crate avow;
+#[cfg(all(test, feature = "serde"))]
+extern crate bincode;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+/// Stands in for the parts of `std` used by this crate when it's built without `std`, so that
+/// the same paths work either way.
+#[cfg(not(feature = "std"))]
+mod std {
+    pub use core::{error, fmt, slice, str};
+    pub use alloc::borrow;
+
+    pub mod collections {
+        pub use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+    }
+}
 
+/// The items in `std`'s prelude which are missing from `core`'s
+#[cfg(not(feature = "std"))]
+mod prelude {
+    pub use alloc::borrow::ToOwned;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+}
+
+mod borrowed;
+mod bounds;
+mod camera;
+mod dedup;
+mod dense;
 mod dot_vox_data;
+mod error;
+mod flatten;
+#[cfg(feature = "std")]
+mod header;
+#[cfg(any(feature = "glam", feature = "mint"))]
+mod interop;
+mod material;
+mod mesh;
 mod palette;
 mod parser;
 mod model;
 mod scene;
+mod validation;
+#[cfg(feature = "std")]
+mod writer;
+
+pub use borrowed::{DotVoxDataRef, MaterialRef, ModelRef, RawChunkRef};
+
+pub use bounds::BoundingBox;
+
+pub use camera::Camera;
+
+pub use dense::{DenseModel, MAX_DENSE_SIZE};
 
 pub use dot_vox_data::DotVoxData;
 
-pub use parser::{Dict, Material};
+#[cfg(feature = "std")]
+pub use error::LoadError;
+pub use error::ParseError;
 
-pub use model::{Model, Size, Voxel};
+pub use flatten::FlattenError;
 
-pub use scene::Transform;
+#[cfg(feature = "std")]
+pub use header::VoxHeader;
 
-use nom::types::CompleteByteSlice;
+pub use material::{Material, MaterialType};
 
-pub use palette::DEFAULT_PALETTE;
+pub use parser::{Dict, RawChunk};
+
+pub use mesh::{Face, Quad};
+
+pub use model::{Model, RawVoxel, Size, Voxel, WorldVoxel};
+
+pub use scene::{Frame, Layer, Node, NodeKind, ScanError, SceneGraph, ShapeInstance, ShapeIter, Transform,
+                MAX_SCENE_DEPTH, MAX_SCENE_VISITS};
+
+pub use palette::{Color, DEFAULT_PALETTE};
+
+pub use validation::ValidationIssue;
 
 use parser::parse_vox_file;
 
+#[cfg(feature = "std")]
 use std::fs::File;
+#[cfg(feature = "std")]
 use std::io::Read;
 
 /// Loads the supplied MagicaVoxel .vox file
@@ -49,8 +130,7 @@ use std::io::Read;
 /// GitHub issue for it.
 ///
 /// # Errors
-/// All errors are strings, and should describe the issue that caused them to 
-/// occur.
+/// If the file can't be read, or can't be parsed as with `load_bytes`.
 ///
 /// # Examples
 ///
@@ -59,10 +139,9 @@ use std::io::Read;
 /// ```
 /// use dot_vox::*;
 ///
-/// let result = load("src/resources/placeholder.vox");
-/// assert_eq!(result.unwrap(), DotVoxData {
-///   version: 150,
-///   models: vec!(
+/// let result = load("src/resources/placeholder.vox").unwrap();
+/// assert_eq!(result.version, 150);
+/// assert_eq!(result.models, vec!(
 ///     Model {
 ///       size: Size { x: 2, y: 2, z: 2 },
 ///       voxels: vec!(
@@ -72,9 +151,9 @@ use std::io::Read;
 ///         Voxel { x: 1, y: 1, z: 0, i: 5 }
 ///       )
 ///     }
-///   ),
-///   palette: DEFAULT_PALETTE.to_vec(),
-///   materials: (0..256).into_iter()
+/// ));
+/// assert_eq!(result.palette, DEFAULT_PALETTE.to_vec());
+/// assert_eq!(result.materials, (0..256)
 ///     .map(|i| Material {
 ///       id: i,
 ///       properties: {
@@ -87,27 +166,71 @@ use std::io::Read;
 ///         map
 ///       }
 ///     })
-///     .collect(),
-///   scene: vec!(
-///     (
-///       Transform {
-///         t: [0, 0, 1],
-///         r: [[1, 0, 0], [0, 1, 0], [0, 0, 1]]
-///       }, 
-///       0,
-///     )
-///   ),
-///   });
+///     .collect::<Vec<_>>());
+/// assert_eq!(result.scene, vec!(
+///   (
+///     Transform {
+///       t: [0, 0, 1],
+///       r: [[1, 0, 0], [0, 1, 0], [0, 0, 1]]
+///     },
+///     0,
+///   )
+/// ));
+/// assert_eq!(result.scene_graph.len(), 4);
+/// assert_eq!(result.layers.len(), 8);
 /// ```
-pub fn load(filename: &str) -> Result<DotVoxData, &'static str> {
-    match File::open(filename) {
-        Ok(mut f) => {
-            let mut buffer = Vec::new();
-            f.read_to_end(&mut buffer).expect("Unable to read file");
-            load_bytes(&buffer)
-        }
-        Err(_) => Err("Unable to load file"),
-    }
+#[cfg(feature = "std")]
+pub fn load(filename: &str) -> Result<DotVoxData, LoadError> {
+    load_from_reader(File::open(filename)?)
+}
+
+/// Reads a .vox file from any source, such as an entry in an archive or a network stream.
+///
+/// The whole of the file is buffered in memory before being parsed, as with `load_bytes`.
+///
+/// # Errors
+/// As with `load_bytes`, or if the reader returns an error.
+///
+/// # Examples
+///
+/// ```
+/// use dot_vox::*;
+///
+/// let bytes: &[u8] = include_bytes!("resources/placeholder.vox");
+/// let result = load_from_reader(bytes).unwrap();
+/// assert_eq!(result, load_bytes(bytes).unwrap());
+/// ```
+#[cfg(feature = "std")]
+pub fn load_from_reader<R: Read>(mut reader: R) -> Result<DotVoxData, LoadError> {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    Ok(load_bytes(&buffer)?)
+}
+
+/// Reads just the version and model sizes of a .vox file.
+///
+/// Rather than buffering the whole file, every chunk other than SIZE chunks (including the
+/// voxels of each model) is skipped over as it's read, so this is a cheap way to list the
+/// dimensions of the models in a file.
+///
+/// # Errors
+/// If the file is not a .vox file, is truncated, or contains chunks which overrun the chunk
+/// containing them. Any error returned by the reader other than `UnexpectedEof` is returned as
+/// `LoadError::Io`.
+///
+/// # Examples
+///
+/// ```
+/// use dot_vox::*;
+///
+/// let bytes: &[u8] = include_bytes!("resources/placeholder.vox");
+/// let header = load_header(bytes).unwrap();
+/// assert_eq!(header.version, 150);
+/// assert_eq!(header.model_sizes, vec![Size { x: 2, y: 2, z: 2 }]);
+/// ```
+#[cfg(feature = "std")]
+pub fn load_header<R: Read>(reader: R) -> Result<VoxHeader, LoadError> {
+    header::read_header(reader)
 }
 
 /// Parses the byte array as a .vox file.
@@ -121,8 +244,12 @@ pub fn load(filename: &str) -> Result<DotVoxData, &'static str> {
 /// GitHub issue for it.
 ///
 /// # Errors
-/// All errors are strings, and should describe the issue that caused them to
-/// occur.
+/// If the bytes aren't a .vox file, or a chunk's size overruns the chunk containing it, or a
+/// model's SIZE or XYZI chunk is truncated. The error gives the offset of the problem and the ID
+/// of the chunk it was found in. Other chunks which can't be parsed are kept in
+/// `unknown_chunks`. A SIZE chunk which isn't immediately followed by an XYZI chunk (or vice
+/// versa) is loaded as an empty model, so that later models keep their IDs; this, and a PACK
+/// chunk declaring the wrong number of models, is recorded in `model_issues`.
 ///
 /// # Examples
 ///
@@ -131,10 +258,9 @@ pub fn load(filename: &str) -> Result<DotVoxData, &'static str> {
 /// ```
 /// use dot_vox::*;
 ///
-/// let result = load_bytes(include_bytes!("resources/placeholder.vox"));
-/// assert_eq!(result.unwrap(), DotVoxData {
-///   version: 150,
-///   models: vec!(
+/// let result = load_bytes(include_bytes!("resources/placeholder.vox")).unwrap();
+/// assert_eq!(result.version, 150);
+/// assert_eq!(result.models, vec!(
 ///     Model {
 ///       size: Size { x: 2, y: 2, z: 2 },
 ///       voxels: vec!(
@@ -144,9 +270,9 @@ pub fn load(filename: &str) -> Result<DotVoxData, &'static str> {
 ///         Voxel { x: 1, y: 1, z: 0, i: 5 }
 ///       )
 ///     }
-///   ),
-///   palette: DEFAULT_PALETTE.to_vec(),
-///   materials: (0..256).into_iter()
+/// ));
+/// assert_eq!(result.palette, DEFAULT_PALETTE.to_vec());
+/// assert_eq!(result.materials, (0..256)
 ///     .map(|i| Material {
 ///       id: i,
 ///       properties: {
@@ -159,32 +285,81 @@ pub fn load(filename: &str) -> Result<DotVoxData, &'static str> {
 ///         map
 ///       }
 ///     })
-///     .collect(),
-///   scene: vec!(
-///     (
-///       Transform {
-///         t: [0, 0, 1],
-///         r: [[1, 0, 0], [0, 1, 0], [0, 0, 1]]
-///       }, 
-///       0,
-///     )
-///   ),
-///   });
+///     .collect::<Vec<_>>());
+/// assert_eq!(result.scene, vec!(
+///   (
+///     Transform {
+///       t: [0, 0, 1],
+///       r: [[1, 0, 0], [0, 1, 0], [0, 0, 1]]
+///     },
+///     0,
+///   )
+/// ));
+/// assert_eq!(result.scene_graph.len(), 4);
+/// assert_eq!(result.layers.len(), 8);
 /// ```
-pub fn load_bytes(bytes: &[u8]) -> Result<DotVoxData, &'static str> {
-    match parse_vox_file(CompleteByteSlice(bytes)) {
-        Ok((_, parsed)) => Ok(parsed),
-        Err(_) => Err("Not a valid MagicaVoxel .vox file"),
-    }
+pub fn load_bytes(bytes: &[u8]) -> Result<DotVoxData, ParseError> {
+    parse_vox_file(bytes)
 }
 
-#[cfg(test)]
+/// Parses the byte array as a .vox file, borrowing from it rather than copying.
+///
+/// Voxels are exposed as slices directly over the file's XYZI chunks, and material properties as
+/// string slices, so loading is much cheaper than with `load_bytes` when only models, the palette
+/// and materials are needed. Every other chunk is left unparsed until the data is converted with
+/// `DotVoxDataRef::to_owned`.
+///
+/// # Errors
+/// As with `load_bytes`.
+///
+/// # Examples
+///
+/// ```
+/// use dot_vox::*;
+///
+/// let bytes = include_bytes!("resources/placeholder.vox");
+/// let result = load_bytes_borrowed(bytes).unwrap();
+/// assert_eq!(result.models[0].size, Size { x: 2, y: 2, z: 2 });
+/// assert_eq!(result.models[0].voxels[0], RawVoxel { x: 0, y: 0, z: 0, i: 226 });
+/// assert_eq!(result.to_owned(), load_bytes(bytes).unwrap());
+/// ```
+pub fn load_bytes_borrowed(bytes: &[u8]) -> Result<DotVoxDataRef<'_>, ParseError> {
+    borrowed::parse_vox_file(bytes)
+}
+
+/// Parses a single chunk from the start of the byte array, without interpreting it, returning
+/// the chunk and the bytes following it.
+///
+/// This allows chunks which this crate doesn't recognise (see `DotVoxData::unknown_chunks`) to
+/// be interpreted, along with their children (see `RawChunk::parse_children`).
+///
+/// # Errors
+/// If the bytes don't start with a complete chunk. Offsets are from the start of `bytes`.
+///
+/// # Examples
+///
+/// ```
+/// use dot_vox::*;
+///
+/// let bytes = include_bytes!("resources/placeholder.vox");
+/// // Skip the magic number and version
+/// let (main, rest) = parse_chunk(&bytes[8..]).unwrap();
+/// assert_eq!(main.id, "MAIN");
+/// assert!(rest.is_empty());
+/// assert_eq!(main.parse_children().unwrap()[0].id, "SIZE");
+/// ```
+pub fn parse_chunk(bytes: &[u8]) -> Result<(RawChunk, &[u8]), ParseError> {
+    let (chunk, rest) = parser::read_chunk(bytes, 0, None)?;
+    Ok((chunk.to_raw(0), rest))
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
   use super::*;
   use avow::vec;
 
     lazy_static! {
-      static ref DEFAULT_MATERIALS: Vec<Material> = (0..256).into_iter()
+      static ref DEFAULT_MATERIALS: Vec<Material> = (0..256)
         .map(|i| Material {
             id: i,
             properties: {
@@ -214,15 +389,23 @@ mod tests {
                     ],
                 },
             ],
-            palette: palette,
-            materials: materials,
+            palette,
+            palette_notes: vec![],
+            index_map: None,
+            materials,
             scene: vec![],
+            scene_graph: SceneGraph::new(),
+            layers: vec![],
+            render_objects: vec![],
+            cameras: vec![],
+            unknown_chunks: vec![],
+            model_issues: vec![],
         }
     }
 
     fn compare_data(actual: DotVoxData, expected: DotVoxData) {
         assert_eq!(actual.version, expected.version);
-        actual.models.into_iter().zip(expected.models.into_iter())
+        actual.models.into_iter().zip(expected.models)
             .for_each(|(actual, expected)| {
                 assert_eq!(actual.size, expected.size);
                 vec::are_eq(actual.voxels, expected.voxels);
@@ -241,43 +424,425 @@ mod tests {
 
     #[test]
     fn not_present_file_causes_error() {
-        let result = load("src/resources/not_here.vox");
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Unable to load file");
+        match load("src/resources/not_here.vox") {
+            Err(LoadError::Io(error)) => assert_eq!(error.kind(), std::io::ErrorKind::NotFound),
+            other => panic!("Expected an I/O error, got {:?}", other),
+        }
     }
 
     #[test]
     fn non_vox_file_causes_error() {
-        let result = load("src/resources/not_a.vox");
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Not a valid MagicaVoxel .vox file");
+        match load("src/resources/not_a.vox") {
+            Err(LoadError::Parse(error)) => {
+                assert_eq!(error, ParseError::new(None, 0, "expected the magic number \"VOX \""));
+                assert_eq!(error.to_string(), "At offset 0x0: expected the magic number \"VOX \"");
+            }
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_voxels_are_reported_with_their_chunk() {
+        let mut bytes = include_bytes!("resources/placeholder.vox").to_vec();
+        // Claim the XYZI chunk has more voxels than it contains
+        assert_eq!(&bytes[44..48], b"XYZI");
+        bytes[56] = 5;
+        let error = load_bytes(&bytes).unwrap_err();
+        assert_eq!(error, ParseError::new(Some("XYZI"), 44, "declared 5 voxels, but only 4 remain in chunk"));
+        assert_eq!(error.to_string(), "XYZI chunk at offset 0x2C: declared 5 voxels, but only 4 remain in chunk");
+        assert_eq!(load_bytes_borrowed(&bytes).unwrap_err(), error);
+    }
+
+    #[test]
+    fn truncated_files_are_reported_with_their_offset() {
+        let bytes = include_bytes!("resources/placeholder.vox");
+        let error = load_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(error.chunk_id, Some("MAIN".to_owned()));
+        assert_eq!(error.offset, 8);
+        assert_eq!(error.message, format!("declared {} bytes of children, but only {} remain",
+                                          bytes.len() - 20, bytes.len() - 21));
+
+        let error = load_bytes(&bytes[..30]).unwrap_err();
+        assert_eq!(error, ParseError::new(Some("MAIN"), 8, "declared 28969 bytes of children, but only 10 remain"));
+        let error = load_bytes(&bytes[..6]).unwrap_err();
+        assert_eq!(error, ParseError::new(None, 4, "expected a version number"));
+    }
+
+    #[test]
+    fn chunks_overrunning_their_parent_are_reported() {
+        let bytes = include_bytes!("resources/placeholder.vox");
+        let (main, _) = parse_chunk(&bytes[8..]).unwrap();
+        let mut children = main.children.clone();
+        children.truncate(30);
+        let chunk = RawChunk { id: "MAIN".to_owned(), content: vec![], children, position: 0 };
+        let error = chunk.parse_children().unwrap_err();
+        assert_eq!(error, ParseError::new(None, 24, "expected a chunk header, but only 6 bytes remain in the MAIN chunk"));
     }
 
     #[test]
     fn can_parse_vox_file_with_palette() {
         let bytes = include_bytes!("resources/placeholder.vox").to_vec();
-        let result = super::parse_vox_file(CompleteByteSlice(&bytes));
+        let result = super::parse_vox_file(&bytes);
         assert!(result.is_ok());
-        let (_, models) = result.unwrap();
+        let models = result.unwrap();
         compare_data(models, placeholder(DEFAULT_PALETTE.to_vec(), DEFAULT_MATERIALS.to_vec()));
     }
 
     #[test]
     fn can_parse_vox_file_with_scene() {
         let bytes = include_bytes!("resources/two-model-scene.vox").to_vec();
-        let result = super::parse_vox_file(CompleteByteSlice(&bytes));
+        let result = super::parse_vox_file(&bytes);
         assert!(result.is_ok());
-        let (_, voxel_data) = result.unwrap();
+        let voxel_data = result.unwrap();
         assert!(voxel_data.scene.len() == 2);
     }
 
+    #[test]
+    fn rotated_models_are_positioned_in_the_world() {
+        let data = load("src/resources/two-model-scene.vox").unwrap();
+        let voxels = data.world_voxels().collect::<Vec<_>>();
+        assert_eq!(voxels.len(), 54);
+
+        // Each model is a 3x3x3 cube centred on its translation, with one odd coloured voxel
+        let first = voxels[..27].iter().filter(|voxel| voxel.i != 78).collect::<Vec<_>>();
+        assert_eq!(first, vec![&WorldVoxel { x: -2, y: 1, z: 5, i: 215 }]);
+        let second = voxels[27..].iter().filter(|voxel| voxel.i != 212).collect::<Vec<_>>();
+        assert_eq!(second, vec![&WorldVoxel { x: 3, y: -1, z: 5, i: 39 }]);
+        for (cube, centre) in voxels.chunks(27).zip(&[[-1, 0, 6], [2, 0, 6]]) {
+            for voxel in cube {
+                assert!((voxel.x - centre[0]).abs() <= 1);
+                assert!((voxel.y - centre[1]).abs() <= 1);
+                assert!((voxel.z - centre[2]).abs() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn even_sized_models_are_positioned_in_the_world() {
+        // A 2x2x2 model which MagicaVoxel saved resting on the ground plane, translated by 1 along Z
+        let data = load("src/resources/placeholder.vox").unwrap();
+        assert_eq!(data.scene[0].0.t, [0, 0, 1]);
+        let mut voxels = data.world_voxels()
+            .map(|voxel| (voxel.x, voxel.y, voxel.z))
+            .collect::<Vec<_>>();
+        voxels.sort();
+        assert_eq!(voxels, vec![(-1, -1, 0), (-1, 0, 1), (0, -1, 1), (0, 0, 0)]);
+    }
+
+    #[test]
+    fn even_sized_models_are_mirrored_in_place() {
+        let model = Model {
+            size: Size { x: 2, y: 4, z: 1 },
+            voxels: vec![
+                Voxel { x: 0, y: 0, z: 0, i: 1 },
+                Voxel { x: 1, y: 3, z: 0, i: 2 },
+            ],
+        };
+        let positions = |r| model
+            .world_voxels(&Transform { t: [10, 20, 30], r })
+            .map(|voxel| (voxel.x, voxel.y, voxel.z, voxel.i))
+            .collect::<Vec<_>>();
+
+        assert_eq!(positions([[1, 0, 0], [0, 1, 0], [0, 0, 1]]), vec![(9, 18, 30, 1), (10, 21, 30, 2)]);
+        assert_eq!(positions([[-1, 0, 0], [0, 1, 0], [0, 0, 1]]), vec![(10, 18, 30, 1), (9, 21, 30, 2)]);
+        assert_eq!(positions([[1, 0, 0], [0, -1, 0], [0, 0, -1]]), vec![(9, 21, 30, 1), (10, 18, 30, 2)]);
+        // A quarter turn about Z swaps the extents along X and Y
+        assert_eq!(positions([[0, -1, 0], [1, 0, 0], [0, 0, 1]]), vec![(11, 19, 30, 1), (8, 20, 30, 2)]);
+    }
+
+    #[test]
+    fn can_parse_vox_file_with_render_objects() {
+        let bytes = include_bytes!("resources/two-model-scene.vox").to_vec();
+        let result = super::parse_vox_file(&bytes);
+        assert!(result.is_ok());
+        let voxel_data = result.unwrap();
+        assert_eq!(voxel_data.render_objects.len(), 13);
+        let background = voxel_data.render_objects.iter()
+            .find(|render_object| render_object.get("_type").map(String::as_str) == Some("_bg"))
+            .unwrap();
+        assert_eq!(background.get("_color"), Some(&"0 0 0".to_owned()));
+    }
+
+    #[test]
+    fn can_parse_vox_file_with_palette_notes() {
+        let result = load("src/resources/palette-notes.vox");
+        assert!(result.is_ok());
+        let data = result.unwrap();
+        assert_eq!(data.palette_notes.len(), 32);
+        assert_eq!(data.color_name(data.models[0].voxels[0].i), Some("emissive"));
+        assert_eq!(data.color_name(7), Some("glass"));
+        assert_eq!(data.color_name(8), None);
+        assert_eq!(data.color_name(255).map(str::len), Some(306));
+    }
+
+    #[test]
+    fn palette_notes_are_empty_when_absent() {
+        let data = load("src/resources/placeholder.vox").unwrap();
+        assert!(data.palette_notes.is_empty());
+        assert_eq!(data.color_name(0), None);
+    }
+
+    #[test]
+    fn can_read_typed_material_properties() {
+        // Saved by MagicaVoxel 0.99, which stores the index of refraction as `_ior` minus 1
+        let data = load("src/resources/metal-material.vox").unwrap();
+        let material = |id| data.materials.iter().find(|material| material.id == id).unwrap();
+
+        let diffuse = material(1);
+        assert_eq!(diffuse.material_type(), MaterialType::Diffuse);
+        assert_eq!(diffuse.roughness(), Some(0.1));
+        assert_eq!(diffuse.metalness(), None);
+        assert_eq!(diffuse.transparency(), None);
+
+        let metal = material(85);
+        assert_eq!(metal.material_type(), MaterialType::Metal);
+        assert_eq!(metal.metalness(), Some(0.526316));
+        assert_eq!(metal.roughness(), Some(0.252632));
+        assert_eq!(metal.ior(), Some(1.3));
+        assert_eq!(metal.transparency(), None);
+
+        let glass = material(249);
+        assert_eq!(glass.material_type(), MaterialType::Glass);
+        assert_eq!(glass.transparency(), Some(0.810526));
+        assert_eq!(glass.roughness(), Some(0.189474));
+        assert_eq!(glass.ior(), Some(1.547368));
+        assert_eq!(glass.metalness(), None);
+
+        let data = load("src/resources/two-model-scene.vox").unwrap();
+        let glass = data.materials.iter().find(|material| material.id == 205).unwrap();
+        assert_eq!(glass.material_type(), MaterialType::Glass);
+        assert_eq!(glass.transparency(), Some(0.45));
+        assert_eq!(glass.roughness(), Some(1.0));
+        assert_eq!(glass.ior(), Some(1.0));
+        assert_eq!(glass.emission(), None);
+    }
+
+    #[test]
+    fn legacy_materials_are_converted() {
+        let data = load("src/resources/legacy-materials.vox").unwrap();
+        assert_eq!(data.materials.len(), 4);
+        let material = |id| data.materials.iter().find(|material| material.id == id).unwrap();
+
+        assert_eq!(material(1).material_type(), MaterialType::Diffuse);
+        assert_eq!(material(1).metalness(), None);
+
+        assert_eq!(material(2).material_type(), MaterialType::Metal);
+        assert_eq!(material(2).metalness(), Some(0.75));
+        assert_eq!(material(2).roughness(), Some(0.25));
+
+        assert_eq!(material(3).material_type(), MaterialType::Glass);
+        assert_eq!(material(3).transparency(), Some(0.5));
+        assert_eq!(material(3).roughness(), Some(0.125));
+        assert_eq!(material(3).ior(), Some(1.5));
+
+        assert_eq!(material(4).material_type(), MaterialType::Emit);
+        assert_eq!(material(4).emission(), Some(0.625));
+        assert_eq!(material(4).properties.get("_att"), Some(&"0.25".to_owned()));
+        assert_eq!(material(4).properties.get("_flux"), Some(&"2".to_owned()));
+        assert_eq!(material(4).properties.get("_glow"), Some(&"0.5".to_owned()));
+    }
+
+    #[test]
+    fn named_groups_and_hidden_flags_come_through() {
+        let data = load("src/resources/named-groups.vox").unwrap();
+        let shapes = data.scene_graph.iter_shapes()
+            .map(|shape| (shape.model_id, shape.name, shape.path, shape.layer_id, shape.hidden))
+            .collect::<Vec<_>>();
+        assert_eq!(shapes, vec![
+            (0, Some("turret_base"), vec![0, 1, 2, 3, 4, 5], Some(0), false),
+            (1, Some("barrel"), vec![0, 1, 2, 3, 6, 7], Some(0), true),
+            (2, Some("left_arm"), vec![0, 1, 8, 9], Some(1), false),
+        ]);
+        assert_eq!(data.scene.len(), 3);
+        assert_eq!(data.scene[0].0.t, [0, 0, 6]);
+
+        // The turret group holds the base and the barrel
+        let turret = data.scene_graph.get(2).unwrap();
+        assert_eq!(turret.name(), Some("turret"));
+        match turret.kind {
+            NodeKind::Transform { child_id, .. } => match &data.scene_graph.get(child_id).unwrap().kind {
+                NodeKind::Group { children_ids } => assert_eq!(children_ids, &vec![4, 6]),
+                kind => panic!("Expected Group node, got {:?}", kind),
+            },
+            ref kind => panic!("Expected Transform node, got {:?}", kind),
+        }
+
+        // The barrel's node is hidden, and the left arm is on a hidden layer
+        let visible = data.visible_shapes().map(|shape| shape.model_id).collect::<Vec<_>>();
+        assert_eq!(visible, vec![0]);
+    }
+
+    #[test]
+    fn voxels_and_models_can_be_found_by_colour_index() {
+        // Two 3x3x3 cubes, each with one voxel of a different colour
+        let data = load("src/resources/two-model-scene.vox").unwrap();
+        let positions = |model: &Model, i| model.voxels_with_index(i)
+            .map(|voxel| (voxel.x, voxel.y, voxel.z))
+            .collect::<Vec<_>>();
+        assert_eq!(positions(&data.models[0], 215), vec![(2, 2, 0)]);
+        assert_eq!(positions(&data.models[1], 39), vec![(0, 0, 2)]);
+        assert_eq!(data.models[0].voxels_with_index(78).count(), 26);
+        assert!(positions(&data.models[0], 39).is_empty());
+        assert_eq!(data.models_using_index(78), vec![0]);
+        assert_eq!(data.models_using_index(215), vec![0]);
+        assert_eq!(data.models_using_index(212), vec![1]);
+        assert!(data.models_using_index(0).is_empty());
+    }
+
+    #[test]
+    fn voxels_are_joined_to_the_material_with_their_file_colour_index() {
+        let is_metal = |material: &Material| material.material_type() == MaterialType::Metal;
+
+        // Saved by MagicaVoxel, with a metal material on the colour of one of its four voxels.
+        // That is colour 216 in the file, which is colour 215 in the palette.
+        let data = load("src/resources/placeholder-with-materials.vox").unwrap();
+        let metal = data.voxels_with_material(is_metal).collect::<Vec<_>>();
+        assert_eq!(metal, vec![(0, &Voxel { x: 0, y: 1, z: 1, i: 215 })]);
+        let material = |id| data.materials.iter().find(|material| material.id == id).unwrap();
+        assert_eq!(material(216).material_type(), MaterialType::Metal);
+        assert_eq!(material(215).material_type(), MaterialType::Diffuse);
+        assert_eq!(data.voxels_with_material(|_| true).count(), 4);
+
+        // Also saved by MagicaVoxel, with every voxel painted with the metal colour 85
+        let data = load("src/resources/metal-material.vox").unwrap();
+        assert_eq!(data.voxels_with_material(is_metal).count(), 20);
+        assert!(data.models[0].voxels.iter().all(|voxel| voxel.i == 84));
+
+        let data = load("src/resources/single-voxel-with-material.vox").unwrap();
+        let metal = data.voxels_with_material(is_metal).collect::<Vec<_>>();
+        assert_eq!(metal, vec![(0, &Voxel { x: 0, y: 0, z: 0, i: 248 })]);
+    }
+
+    #[test]
+    fn palette_colours_can_be_unpacked() {
+        let data = load("src/resources/placeholder.vox").unwrap();
+        assert_eq!(data.color(0), Color { r: 255, g: 255, b: 255, a: 255 });
+        assert_eq!(data.color(1), Color { r: 255, g: 255, b: 204, a: 255 });
+        let bytes = data.palette_as_rgba_bytes();
+        assert_eq!(&bytes[4..8], &[255, 255, 204, 255]);
+        assert_eq!(&bytes[1020..], &[0, 0, 0, 0]);
+
+        let data = DotVoxData { palette: vec![0x0403_0201], ..data };
+        assert_eq!(data.color(1), Color::default());
+        assert_eq!(&data.palette_as_rgba_bytes()[..8], &[1, 2, 3, 4, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn can_parse_vox_file_with_index_map() {
+        let result = load("src/resources/index-map.vox");
+        assert!(result.is_ok());
+        let data = result.unwrap();
+        let index_map = data.index_map.expect("IMAP chunk should be parsed");
+        assert_eq!(index_map[0], 255);
+        assert_eq!(index_map[255], 0);
+        // The palette itself stays in file order
+        assert_eq!(data.palette[0], 0xff00_0000);
+        let remapped = data.remapped_palette();
+        assert_eq!(remapped.len(), 256);
+        assert_eq!(remapped[0], 0xff00_00fe);
+        assert_eq!(remapped[254], 0xff00_0000);
+        assert_eq!(remapped[255], 0xff00_00ff);
+        assert_eq!(data.effective_color(0), 0xff00_00fe);
+        assert_eq!(data.effective_color(254), data.palette[0]);
+        // Voxel indices are left untouched, but their row names follow the display order
+        assert_eq!(data.models[0].voxels[0].i, 0);
+        assert_eq!(data.color_name(0), Some("last row"));
+        assert_eq!(data.color_name(253), Some("first row"));
+    }
+
+    #[test]
+    fn palette_is_in_display_order_without_an_index_map() {
+        let data = load("src/resources/placeholder.vox").unwrap();
+        assert_eq!(data.index_map, None);
+        assert_eq!(data.remapped_palette(), data.palette);
+        assert_eq!(data.effective_color(3), data.palette[3]);
+    }
+
+    #[test]
+    fn unknown_chunks_are_kept() {
+        let data = load("src/resources/extension-chunks.vox").unwrap();
+        assert_eq!(data.models.len(), 1);
+        assert_eq!(data.unknown_chunks.len(), 2);
+        let extension = &data.unknown_chunks[0];
+        assert_eq!(extension.id, "XTRA");
+        assert_eq!(extension.content, b"hello".to_vec());
+        assert_eq!(extension.parse_children(), Ok(vec![RawChunk {
+            id: "XTRC".to_owned(),
+            content: vec![1, 2, 3],
+            children: vec![],
+            position: 0,
+        }]));
+        assert_eq!(extension.position, 2);
+        assert_eq!(data.unknown_chunks[1].id, "xEMP");
+        assert_eq!(data.unknown_chunks[1].position, 3);
+    }
+
+    #[test]
+    fn can_parse_vox_file_with_cameras() {
+        let result = load("src/resources/cameras.vox");
+        assert!(result.is_ok());
+        let cameras = result.unwrap().cameras;
+        assert_eq!(cameras.len(), 2);
+        assert_eq!(cameras[0].focus(), Some([0.0, 0.0, 12.5]));
+        // Malformed attributes don't stop the rest of the file from loading
+        assert_eq!(cameras[1].id, 1);
+        assert_eq!(cameras[1].mode(), Some("orth"));
+        assert_eq!(cameras[1].focus(), None);
+    }
+
+    #[test]
+    fn can_parse_vox_file_with_layers() {
+        let bytes = include_bytes!("resources/three-layers.vox").to_vec();
+        let result = super::parse_vox_file(&bytes);
+        assert!(result.is_ok());
+        let voxel_data = result.unwrap();
+        let layers = voxel_data.layers.iter()
+            .map(|layer| (layer.id, layer.name(), layer.hidden()))
+            .collect::<Vec<_>>();
+        assert_eq!(layers, vec![
+            (0, Some("collision"), false),
+            (1, Some("decoration"), true),
+            (2, Some("lights"), false),
+        ]);
+        match voxel_data.scene_graph.get(2).map(|node| &node.kind) {
+            Some(NodeKind::Transform { layer_id, .. }) => assert_eq!(*layer_id, 1),
+            node => panic!("Expected Transform node, got {:?}", node),
+        }
+    }
+
+    #[test]
+    fn shapes_can_be_joined_to_their_layers() {
+        let mut data = load("src/resources/three-layers.vox").unwrap();
+        fn layer_names(data: &DotVoxData) -> Vec<(usize, Option<&str>)> {
+            data.scene_graph.iter_shapes()
+                .map(|shape| (shape.model_id, shape.layer_id.and_then(|id| data.layer(id)).and_then(Layer::name)))
+                .collect()
+        }
+        assert_eq!(layer_names(&data), vec![(0, Some("decoration"))]);
+        assert_eq!(data.visible_shapes().count(), 0);
+
+        // A layer without a LAYR chunk is treated as visible
+        data.layers.retain(|layer| layer.id != 1);
+        assert_eq!(data.layer(1), None);
+        assert_eq!(layer_names(&data), vec![(0, None)]);
+        assert_eq!(data.visible_shapes().map(|shape| shape.layer_id).collect::<Vec<_>>(), vec![Some(1)]);
+    }
+
+    #[test]
+    fn files_without_layers_have_none() {
+        let data = load("src/resources/legacy-materials.vox").unwrap();
+        assert!(data.layers.is_empty());
+        assert_eq!(data.layer(0), None);
+        assert_eq!(data.visible_shapes().count(), data.scene.len());
+    }
+
     #[test]
     fn can_parse_vox_file_with_materials() {
-        let _log = env_logger::init();
+        env_logger::init();
         let bytes = include_bytes!("resources/placeholder-with-materials.vox").to_vec();
-        let result = super::parse_vox_file(CompleteByteSlice(&bytes));
+        let result = super::parse_vox_file(&bytes);
         assert!(result.is_ok());
-        let (_, voxel_data) = result.unwrap();
+        let voxel_data = result.unwrap();
         let mut materials: Vec<Material> = DEFAULT_MATERIALS.to_vec();
         materials[216] = Material{
             id: 216,
@@ -294,4 +859,93 @@ mod tests {
         };
         compare_data(voxel_data, placeholder(DEFAULT_PALETTE.to_vec(), materials));
     }
+
+    /// Runs everything which reads data from a file, none of which may panic whatever the input.
+    fn load_everything(bytes: &[u8]) {
+        if let Ok(data) = load_bytes(bytes) {
+            data.validate();
+            data.world_voxels().count();
+            let _ = data.scene_graph.try_collapse_to_vec();
+            data.write_vox(Vec::new()).unwrap();
+        }
+        if let Ok(data) = load_bytes_borrowed(bytes) {
+            data.to_owned();
+        }
+        let _ = load_header(bytes);
+    }
+
+    fn fixtures() -> Vec<(String, Vec<u8>)> {
+        let mut fixtures = vec![];
+        for entry in std::fs::read_dir("src/resources").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() == Some("vox".as_ref()) {
+                fixtures.push((path.display().to_string(), std::fs::read(&path).unwrap()));
+            }
+        }
+        fixtures
+    }
+
+    #[test]
+    fn truncated_files_never_panic() {
+        for (name, bytes) in fixtures() {
+            // Every length near the start of the file, where the chunk headers are, but only a
+            // sample of lengths through the rest of large files
+            let lengths = (0..bytes.len().min(2048)).chain((2048..bytes.len()).step_by(61));
+            for length in lengths {
+                let result = std::panic::catch_unwind(|| load_everything(&bytes[..length]));
+                assert!(result.is_ok(), "{} panicked when truncated to {} bytes", name, length);
+            }
+        }
+    }
+
+    #[test]
+    fn groups_of_missing_children_load_quickly() {
+        // A group whose children don't exist, each of which used to restart the scan of the
+        // scene graph, making loading quadratic in the number of children
+        const CHILDREN: u32 = 100_000;
+        let mut content = vec![0, 0, 0, 0, 0, 0, 0, 0];
+        content.extend_from_slice(&CHILDREN.to_le_bytes());
+        for child in 1..=CHILDREN {
+            content.extend_from_slice(&child.to_le_bytes());
+        }
+        let mut main = b"nGRP".to_vec();
+        main.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        main.extend_from_slice(&[0; 4]);
+        main.extend(content);
+        let mut bytes = b"VOX \x96\0\0\0MAIN\0\0\0\0".to_vec();
+        bytes.extend_from_slice(&(main.len() as u32).to_le_bytes());
+        bytes.extend(main);
+
+        let start = std::time::Instant::now();
+        let data = load_bytes(&bytes).unwrap();
+        assert!(data.scene.is_empty());
+        // One error for each missing child, however the graph is scanned
+        assert_eq!(data.scene_graph.try_iter_shapes().filter(Result::is_err).count(), CHILDREN as usize);
+        assert_eq!(data.validate().len(), CHILDREN as usize);
+        load_everything(&bytes);
+        assert!(start.elapsed() < std::time::Duration::from_secs(5), "took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn corrupted_files_never_panic() {
+        // A fixed xorshift generator, so that any failure is reproducible
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for (name, bytes) in fixtures().into_iter().filter(|(_, bytes)| bytes.len() < 2048) {
+            for _ in 0..500 {
+                let mut corrupted = bytes.clone();
+                for _ in 0..=random() % 4 {
+                    let index = random() as usize % corrupted.len();
+                    corrupted[index] = random() as u8;
+                }
+                let result = std::panic::catch_unwind(|| load_everything(&corrupted));
+                assert!(result.is_ok(), "{} panicked when corrupted to {:?}", name, corrupted);
+            }
+        }
+    }
 }
@@ -0,0 +1,311 @@
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use nom::types::CompleteByteSlice;
+use ::parser::{le_u32, parse_dict, Dict};
+
+/*
+Material Chunk : "MATL"
+
+int32	: material id
+DICT	: material properties
+	  (_type : str) _diffuse, _metal, _glass, _emit, _blend, _media, _cloud
+	  (_weight : float) range 0 ~ 1
+	  (_rough : float)
+	  (_spec : float)
+	  (_ior : float)
+	  (_att : float)
+	  (_flux : float)
+	  (_plastic)
+	  (_metal : float)
+	  (_trans : float)
+	  (_emit : float)
+	  (_ri : float)
+*/
+
+/*
+Legacy Material Chunk : "MATT"
+
+int32	: id [1-255]
+int32	: material type
+	  0 : diffuse
+	  1 : metal
+	  2 : glass
+	  3 : emissive
+float	: material weight (0.0 - 1.0]
+int32	: property bits : set if value is saved in next section
+	  bit(0) : Plastic
+	  bit(1) : Roughness
+	  bit(2) : Specular
+	  bit(3) : IOR
+	  bit(4) : Attenuation
+	  bit(5) : Power
+	  bit(6) : Glow
+	  bit(7) : isTotalPower (*no value)
+float * N	: property values
+*/
+
+/// The MATL property names for each MATT property bit which is followed by a value. Glow has no
+/// MATL equivalent, so is kept under its own name.
+const LEGACY_PROPERTIES: [&str; 7] = ["_plastic", "_rough", "_spec", "_ior", "_att", "_flux", "_glow"];
+
+/// A material used to render this model.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Material {
+    /// The Material's ID
+    pub id: u32,
+    /// Properties of the material, mapped by property name.
+    pub properties: Dict,
+}
+
+/// The kind of surface a `Material` describes, as selected in MagicaVoxel's material panel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MaterialType {
+    /// A plain, non-reflective surface. Materials with no `_type` are diffuse.
+    Diffuse,
+    /// A metallic surface; see `Material::metalness`.
+    Metal,
+    /// A plastic surface.
+    Plastic,
+    /// A transparent surface; see `Material::transparency` and `Material::ior`.
+    Glass,
+    /// A light-emitting surface; see `Material::emission`.
+    Emit,
+    /// A blend of metal, glass and emission.
+    Blend,
+    /// A volumetric medium such as fog.
+    Media,
+    /// A cloud-like volumetric medium.
+    Cloud,
+    /// A type not known to this crate, holding the raw `_type` value.
+    Unknown(String),
+}
+
+impl MaterialType {
+    fn from_property(value: &str) -> MaterialType {
+        match value {
+            "_diffuse" => MaterialType::Diffuse,
+            "_metal" => MaterialType::Metal,
+            "_plastic" => MaterialType::Plastic,
+            "_glass" => MaterialType::Glass,
+            "_emit" => MaterialType::Emit,
+            "_blend" => MaterialType::Blend,
+            "_media" => MaterialType::Media,
+            "_cloud" => MaterialType::Cloud,
+            other => MaterialType::Unknown(other.to_owned()),
+        }
+    }
+}
+
+impl Material {
+    /// The type of this material, from its `_type` property.
+    pub fn material_type(&self) -> MaterialType {
+        self.properties
+            .get("_type")
+            .map_or(MaterialType::Diffuse, |value| MaterialType::from_property(value))
+    }
+
+    /// The roughness of the surface, from 0 (smooth) to 1.
+    pub fn roughness(&self) -> Option<f32> {
+        self.unit_property("_rough")
+    }
+
+    /// How metallic the surface is, from 0 to 1.
+    ///
+    /// Files written before the per-property keys were introduced only store this as the
+    /// `_weight` of a metal material, which is used as a fallback.
+    pub fn metalness(&self) -> Option<f32> {
+        self.unit_property("_metal").or_else(|| self.weight_for(MaterialType::Metal))
+    }
+
+    /// How strongly the surface emits light, from 0 to 1.
+    ///
+    /// Falls back to the `_weight` of an emissive material, as with `metalness`.
+    pub fn emission(&self) -> Option<f32> {
+        self.unit_property("_emit").or_else(|| self.weight_for(MaterialType::Emit))
+    }
+
+    /// How transparent the surface is, from 0 (opaque) to 1.
+    ///
+    /// Falls back to the `_weight` of a glass material, as with `metalness`.
+    pub fn transparency(&self) -> Option<f32> {
+        self.unit_property("_trans").or_else(|| self.weight_for(MaterialType::Glass))
+    }
+
+    /// The index of refraction, as shown in MagicaVoxel (so never less than 1).
+    ///
+    /// This is read from `_ri` if present. Otherwise it is read from `_ior`, which stores the
+    /// index of refraction minus 1.
+    pub fn ior(&self) -> Option<f32> {
+        self.property("_ri")
+            .or_else(|| self.property("_ior").map(|ior| ior + 1.0))
+            .map(|ri| ri.max(1.0))
+    }
+
+    fn property(&self, key: &str) -> Option<f32> {
+        self.properties
+            .get(key)
+            .and_then(|value| value.parse::<f32>().ok())
+            .filter(|value| value.is_finite())
+    }
+
+    fn unit_property(&self, key: &str) -> Option<f32> {
+        self.property(key).map(|value| value.clamp(0.0, 1.0))
+    }
+
+    fn weight_for(&self, material_type: MaterialType) -> Option<f32> {
+        if self.material_type() == material_type {
+            self.unit_property("_weight")
+        } else {
+            None
+        }
+    }
+}
+
+named!(pub parse_material <CompleteByteSlice, Material>, do_parse!(
+    id: le_u32 >>
+    properties: parse_dict >>
+    (Material { id, properties })
+));
+
+named!(le_f32 <CompleteByteSlice, f32>, map!(le_u32, f32::from_bits));
+
+named!(pub parse_legacy_material <CompleteByteSlice, Material>, do_parse!(
+    id: le_u32 >>
+    material_type: le_u32 >>
+    weight: le_f32 >>
+    property_bits: le_u32 >>
+    values: many_m_n!(
+        legacy_value_count(property_bits),
+        legacy_value_count(property_bits),
+        le_f32
+    ) >>
+    (build_legacy_material(id, material_type, weight, property_bits, &values))
+));
+
+fn legacy_value_count(property_bits: u32) -> usize {
+    (property_bits & 0x7f).count_ones() as usize
+}
+
+/// Converts a MATT chunk into the properties the equivalent MATL chunk would have.
+fn build_legacy_material(id: u32, material_type: u32, weight: f32, property_bits: u32,
+                         values: &[f32]) -> Material {
+    let mut properties = Dict::new();
+    let (type_name, weight_name) = match material_type {
+        1 => ("_metal", Some("_metal")),
+        2 => ("_glass", Some("_trans")),
+        3 => ("_emit", Some("_emit")),
+        _ => ("_diffuse", None),
+    };
+    properties.insert("_type".to_owned(), type_name.to_owned());
+    properties.insert("_weight".to_owned(), weight.to_string());
+    if let Some(weight_name) = weight_name {
+        properties.insert(weight_name.to_owned(), weight.to_string());
+    }
+    let names = LEGACY_PROPERTIES
+        .iter()
+        .enumerate()
+        .filter(|&(bit, _)| property_bits & (1 << bit) != 0);
+    for ((_, name), value) in names.zip(values) {
+        properties.insert((*name).to_owned(), value.to_string());
+    }
+    Material { id, properties }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn material(properties: &[(&str, &str)]) -> Material {
+        Material {
+            id: 1,
+            properties: properties
+                .iter()
+                .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn can_parse_a_material_chunk() {
+        let bytes = include_bytes!("resources/valid_material.bytes").to_vec();
+        let result = parse_material(CompleteByteSlice(&bytes));
+        match result {
+            Ok((_, material)) => {
+                assert_eq!(material.id, 0);
+                assert_eq!(material.properties.get("_type"), Some(&"_diffuse".to_owned()));
+                assert_eq!(material.properties.get("_weight"), Some(&"1".to_owned()));
+                assert_eq!(material.properties.get("_rough"), Some(&"0.1".to_owned()));
+                assert_eq!(material.properties.get("_spec"), Some(&"0.5".to_owned()));
+                assert_eq!(material.properties.get("_ior"), Some(&"0.3".to_owned()));
+            }
+            _ => panic!("Expected Done, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn can_parse_a_legacy_material_chunk() {
+        let bytes = include_bytes!("resources/valid_legacy_material.bytes").to_vec();
+        // Skip the chunk id and sizes
+        let result = parse_legacy_material(CompleteByteSlice(&bytes[12..]));
+        match result {
+            Ok((rest, material)) => {
+                assert!(rest.is_empty());
+                assert_eq!(material.id, 2);
+                assert_eq!(material.properties.get("_type"), Some(&"_metal".to_owned()));
+                assert_eq!(material.properties.get("_weight"), Some(&"0.75".to_owned()));
+                assert_eq!(material.properties.get("_plastic"), Some(&"1".to_owned()));
+                assert_eq!(material.properties.get("_rough"), Some(&"0.25".to_owned()));
+                assert_eq!(material.properties.get("_spec"), Some(&"0.5".to_owned()));
+                assert_eq!(material.metalness(), Some(0.75));
+                assert_eq!(material.properties.len(), 6);
+            }
+            _ => panic!("Expected Done, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn missing_type_is_diffuse() {
+        assert_eq!(material(&[]).material_type(), MaterialType::Diffuse);
+        assert_eq!(
+            material(&[("_type", "_sparkly")]).material_type(),
+            MaterialType::Unknown("_sparkly".to_owned())
+        );
+    }
+
+    #[test]
+    fn values_are_clamped_to_their_range() {
+        let material = material(&[
+            ("_type", "_glass"),
+            ("_rough", "1.5"),
+            ("_trans", "-0.2"),
+            ("_ior", "-0.5"),
+        ]);
+        assert_eq!(material.roughness(), Some(1.0));
+        assert_eq!(material.transparency(), Some(0.0));
+        assert_eq!(material.ior(), Some(1.0));
+    }
+
+    #[test]
+    fn malformed_values_are_none() {
+        let material = material(&[("_rough", "rough"), ("_metal", "NaN"), ("_emit", "")]);
+        assert_eq!(material.roughness(), None);
+        assert_eq!(material.metalness(), None);
+        assert_eq!(material.emission(), None);
+        assert_eq!(material.ior(), None);
+    }
+
+    #[test]
+    fn weight_is_only_used_for_the_matching_type() {
+        let metal = material(&[("_type", "_metal"), ("_weight", "0.6")]);
+        assert_eq!(metal.metalness(), Some(0.6));
+        assert_eq!(metal.emission(), None);
+        assert_eq!(metal.transparency(), None);
+
+        let emit = material(&[("_type", "_emit"), ("_weight", "0.8"), ("_flux", "2")]);
+        assert_eq!(emit.emission(), Some(0.8));
+        assert_eq!(emit.metalness(), None);
+        assert_eq!(material(&[("_type", "_emit"), ("_weight", "0.8"), ("_emit", "0.3")]).emission(), Some(0.3));
+    }
+}
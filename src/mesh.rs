@@ -0,0 +1,268 @@
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use {DenseModel, Model, Size, MAX_DENSE_SIZE};
+
+/// The direction in which a `Quad` faces
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
+    /// Facing towards positive x.
+    PosX,
+    /// Facing towards negative x.
+    NegX,
+    /// Facing towards positive y.
+    PosY,
+    /// Facing towards negative y.
+    NegY,
+    /// Facing towards positive z (up, in MagicaVoxel).
+    PosZ,
+    /// Facing towards negative z.
+    NegZ,
+}
+
+impl Face {
+    /// Every face, in the order quads are produced by `Model::extract_quads`.
+    pub const ALL: [Face; 6] = [Face::PosX, Face::NegX, Face::PosY, Face::NegY, Face::PosZ, Face::NegZ];
+
+    /// The axis the face points along (0 for x, 1 for y and 2 for z).
+    pub fn axis(self) -> usize {
+        match self {
+            Face::PosX | Face::NegX => 0,
+            Face::PosY | Face::NegY => 1,
+            Face::PosZ | Face::NegZ => 2,
+        }
+    }
+
+    /// Whether the face points towards the positive end of its axis.
+    pub fn is_positive(self) -> bool {
+        match self {
+            Face::PosX | Face::PosY | Face::PosZ => true,
+            Face::NegX | Face::NegY | Face::NegZ => false,
+        }
+    }
+
+    /// The unit vector the face points along.
+    pub fn normal(self) -> [i32; 3] {
+        let mut normal = [0; 3];
+        normal[self.axis()] = if self.is_positive() { 1 } else { -1 };
+        normal
+    }
+}
+
+/// A rectangle on the surface of a model, covering the faces of one or more voxels of the same
+/// colour
+///
+/// The quad lies in the plane at `position[face.axis()]`, measured in voxel corners (so the
+/// positive x face of the voxel at x = 3 lies at x = 4). Across that plane, the quad covers the
+/// two other axes in increasing order (y and z for an x face, z and x for a y face and x and y
+/// for a z face), starting at `position` and extending by `size`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quad {
+    /// The corner of the quad with the smallest coordinates.
+    pub position: [u32; 3],
+    /// The extent of the quad along the two axes it covers.
+    pub size: [u32; 2],
+    /// The direction the quad faces.
+    pub face: Face,
+    /// Index in the Color Palette, as in `Voxel::i`.
+    pub i: u8,
+}
+
+impl Model {
+    /// Extracts the visible surface of this model as quads, merging adjacent faces of the same
+    /// colour which face the same way (greedy meshing).
+    ///
+    /// Faces between two voxels are skipped, so only the outer surface (and the surface of any
+    /// holes) is produced. Voxels outside the model's size are ignored, and where several voxels
+    /// share a position the last one wins. Quads are grouped by face, in the order of
+    /// `Face::ALL`.
+    pub fn extract_quads(&self) -> Vec<Quad> {
+        // Voxel coordinates can't exceed MAX_DENSE_SIZE, so larger sizes can be clamped without
+        // dropping any voxels.
+        let size = Size {
+            x: self.size.x.min(MAX_DENSE_SIZE),
+            y: self.size.y.min(MAX_DENSE_SIZE),
+            z: self.size.z.min(MAX_DENSE_SIZE),
+        };
+        let dense = Model { size, voxels: self.voxels.clone() }
+            .to_dense()
+            .expect("clamped size fits in a dense model");
+        let mut quads = vec![];
+        for &face in &Face::ALL {
+            extract_face(&dense, face, &mut quads);
+        }
+        quads
+    }
+}
+
+/// Extracts every quad facing the given way, one slice of the model at a time.
+fn extract_face(dense: &DenseModel, face: Face, quads: &mut Vec<Quad>) {
+    let size = dense.size();
+    let dimensions = [size.x, size.y, size.z];
+    let axis = face.axis();
+    let u_axis = (axis + 1) % 3;
+    let v_axis = (axis + 2) % 3;
+    let (width, height) = (dimensions[u_axis] as usize, dimensions[v_axis] as usize);
+    let at = |d: u32, u: u32, v: u32| {
+        let mut position = [0; 3];
+        position[axis] = d;
+        position[u_axis] = u;
+        position[v_axis] = v;
+        position
+    };
+    let voxels = dense.as_slice();
+    let strides = [1, dimensions[0] as usize, dimensions[0] as usize * dimensions[1] as usize];
+    let (d_stride, u_stride, v_stride) = (strides[axis], strides[u_axis], strides[v_axis]);
+    let depth = dimensions[axis] as usize;
+
+    // The colour of each visible face in the current slice, or 0 if there's no visible face
+    let mut mask = vec![0u8; width * height];
+    for d in 0..depth {
+        let has_neighbour = if face.is_positive() { d + 1 < depth } else { d > 0 };
+        for v in 0..height {
+            for u in 0..width {
+                let offset = d * d_stride + u * u_stride + v * v_stride;
+                let neighbour = match (has_neighbour, face.is_positive()) {
+                    (false, _) => 0,
+                    (true, true) => voxels[offset + d_stride],
+                    (true, false) => voxels[offset - d_stride],
+                };
+                mask[u + v * width] = if neighbour == 0 { voxels[offset] } else { 0 };
+            }
+        }
+
+        for v in 0..height {
+            let mut u = 0;
+            while u < width {
+                let colour = mask[u + v * width];
+                if colour == 0 {
+                    u += 1;
+                    continue;
+                }
+                let mut quad_width = 1;
+                while u + quad_width < width && mask[u + quad_width + v * width] == colour {
+                    quad_width += 1;
+                }
+                let mut quad_height = 1;
+                while v + quad_height < height
+                    && mask[u + (v + quad_height) * width..][..quad_width].iter().all(|&c| c == colour) {
+                    quad_height += 1;
+                }
+                for row in v..v + quad_height {
+                    for cell in &mut mask[u + row * width..][..quad_width] {
+                        *cell = 0;
+                    }
+                }
+                let plane = if face.is_positive() { d + 1 } else { d } as u32;
+                quads.push(Quad {
+                    position: at(plane, u as u32, v as u32),
+                    size: [quad_width as u32, quad_height as u32],
+                    face,
+                    i: colour - 1,
+                });
+                u += quad_width;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use {load, Voxel};
+
+    fn model(size: Size, voxels: &[(u8, u8, u8, u8)]) -> Model {
+        Model {
+            size,
+            voxels: voxels.iter().map(|&(x, y, z, i)| Voxel { x, y, z, i }).collect(),
+        }
+    }
+
+    fn area(quads: &[Quad]) -> u32 {
+        quads.iter().map(|quad| quad.size[0] * quad.size[1]).sum()
+    }
+
+    #[test]
+    fn a_single_voxel_has_six_faces() {
+        let quads = model(Size { x: 1, y: 1, z: 1 }, &[(0, 0, 0, 7)]).extract_quads();
+        assert_eq!(quads.len(), 6);
+        assert!(quads.iter().all(|quad| quad.size == [1, 1] && quad.i == 7));
+        assert_eq!(quads[0], Quad { position: [1, 0, 0], size: [1, 1], face: Face::PosX, i: 7 });
+        assert_eq!(quads[1], Quad { position: [0, 0, 0], size: [1, 1], face: Face::NegX, i: 7 });
+    }
+
+    #[test]
+    fn a_full_cube_is_merged_into_six_quads() {
+        let mut voxels = vec![];
+        for x in 0..2 {
+            for y in 0..2 {
+                for z in 0..2 {
+                    voxels.push((x, y, z, 3));
+                }
+            }
+        }
+        let quads = model(Size { x: 2, y: 2, z: 2 }, &voxels).extract_quads();
+        assert_eq!(quads.len(), 6);
+        assert!(quads.iter().all(|quad| quad.size == [2, 2]));
+        let top = quads.iter().find(|quad| quad.face == Face::PosZ).unwrap();
+        assert_eq!(top.position, [0, 0, 2]);
+    }
+
+    #[test]
+    fn different_colours_are_not_merged() {
+        let size = Size { x: 2, y: 1, z: 1 };
+        assert_eq!(model(size, &[(0, 0, 0, 1), (1, 0, 0, 1)]).extract_quads().len(), 6);
+        assert_eq!(model(size, &[(0, 0, 0, 1), (1, 0, 0, 2)]).extract_quads().len(), 10);
+    }
+
+    #[test]
+    fn holes_have_inner_faces() {
+        let mut voxels = vec![];
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    if (x, y, z) != (1, 1, 1) {
+                        voxels.push((x, y, z, 0));
+                    }
+                }
+            }
+        }
+        let quads = model(Size { x: 3, y: 3, z: 3 }, &voxels).extract_quads();
+        assert_eq!(quads.len(), 12);
+        assert_eq!(area(&quads), 6 * 9 + 6);
+    }
+
+    #[test]
+    fn surface_area_matches_the_exposed_faces() {
+        let data = load("src/resources/two-model-scene.vox").unwrap();
+        for model in &data.models {
+            let dense = model.to_dense().unwrap();
+            let size = dense.size();
+            let filled = |x: i64, y: i64, z: i64| {
+                x >= 0 && y >= 0 && z >= 0
+                    && dense.get(x as u32, y as u32, z as u32).is_some_and(|index| index != 0)
+            };
+            let mut exposed = 0;
+            for x in 0..size.x as i64 {
+                for y in 0..size.y as i64 {
+                    for z in 0..size.z as i64 {
+                        if filled(x, y, z) {
+                            let neighbours = [
+                                (x + 1, y, z), (x - 1, y, z), (x, y + 1, z),
+                                (x, y - 1, z), (x, y, z + 1), (x, y, z - 1),
+                            ];
+                            exposed += neighbours.iter().filter(|&&(x, y, z)| !filled(x, y, z)).count();
+                        }
+                    }
+                }
+            }
+            assert_eq!(area(&model.extract_quads()), exposed as u32);
+        }
+    }
+
+    #[test]
+    fn large_models_can_be_meshed() {
+        let quads = model(Size { x: 256, y: 256, z: 256 }, &[(255, 255, 255, 1)]).extract_quads();
+        assert_eq!(quads.len(), 6);
+        assert_eq!(quads[0].position, [256, 255, 255]);
+    }
+}
@@ -1,8 +1,12 @@
+#[cfg(not(feature = "std"))]
+use prelude::*;
 use nom::types::CompleteByteSlice;
-use ::parser::{le_u8, le_u32};
+use ::parser::{le_count, le_u8, le_u32};
+use Transform;
 
 /// A renderable voxel Model
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Model {
     /// The size of the model in voxels
     pub size: Size,
@@ -14,6 +18,7 @@ pub struct Model {
 ///
 /// Indicates the size of the model in Voxels.
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Size {
     /// The width of the model in voxels.
     pub x: u32,
@@ -23,10 +28,18 @@ pub struct Size {
     pub z: u32,
 }
 
+impl Size {
+    /// Whether the voxel lies within the bounds of a model of this size.
+    pub fn contains(&self, voxel: &Voxel) -> bool {
+        (voxel.x as u32) < self.x && (voxel.y as u32) < self.y && (voxel.z as u32) < self.z
+    }
+}
+
 /// A Voxel
 ///
 /// A Voxel is a point in 3D space, with an indexed colour attached.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Voxel {
     /// The X coordinate for the Voxel
     pub x: u8,
@@ -41,6 +54,100 @@ pub struct Voxel {
     pub i: u8,
 }
 
+/// A Voxel exactly as stored in an XYZI chunk, so that a chunk's voxels can be read in place
+///
+/// Unlike `Voxel`, the palette index is not adjusted, so runs from 1-255 as in the source file:
+/// `RawVoxel::i` is one more than the index into `DotVoxData::palette`. Converting into a
+/// `Voxel` subtracts the 1.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RawVoxel {
+    /// The X coordinate for the Voxel
+    pub x: u8,
+    /// The Y coordinate for the Voxel
+    pub y: u8,
+    /// The Z coordinate for the Voxel
+    pub z: u8,
+    /// Index in the Color Palette, as stored in the source file (so one more than `Voxel::i`).
+    pub i: u8,
+}
+
+impl From<RawVoxel> for Voxel {
+    fn from(voxel: RawVoxel) -> Voxel {
+        Voxel { x: voxel.x, y: voxel.y, z: voxel.z, i: voxel.i.saturating_sub(1) }
+    }
+}
+
+/// A Voxel which has been positioned in the world by the scene graph
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WorldVoxel {
+    /// The X coordinate for the Voxel
+    pub x: i32,
+    /// The Y coordinate for the Voxel
+    pub y: i32,
+    /// The Z coordinate for the Voxel
+    pub z: i32,
+    /// Index in the Color Palette, as in `Voxel::i`.
+    pub i: u8,
+}
+
+impl Model {
+    /// Removes every voxel which lies outside the bounds given by this model's size, as some
+    /// exporters produce such voxels.
+    pub fn retain_valid_voxels(&mut self) {
+        let size = self.size;
+        self.voxels.retain(|voxel| size.contains(voxel));
+    }
+
+    /// An iterator over the voxels of this model with the given colour index, as stored in
+    /// `Voxel::i` (so 1 less than the index in the source file).
+    pub fn voxels_with_index(&self, i: u8) -> impl Iterator<Item = &Voxel> {
+        self.voxels.iter().filter(move |voxel| voxel.i == i)
+    }
+
+    /// An iterator over the voxels of this model, positioned in the world by the supplied
+    /// transformation (such as one from `DotVoxData::scene`).
+    ///
+    /// MagicaVoxel rotates each model about its centre, and places that centre at the
+    /// transformation's translation, so a voxel's world position is determined by both the
+    /// transformation and the size of the model. For an unrotated model, the voxel at
+    /// `size / 2` (rounding down) lands exactly on the translation. Rotations keep the model
+    /// within the same box, so mirroring a model with an even size along an axis doesn't shift
+    /// it by a voxel.
+    pub fn world_voxels<'a>(&'a self, transform: &Transform) -> impl Iterator<Item = WorldVoxel> + 'a {
+        let size = self.size;
+        let transform = *transform;
+        self.voxels.iter().map(move |voxel| {
+            let [x, y, z] = world_position(size, &transform, [voxel.x as i32, voxel.y as i32, voxel.z as i32]);
+            WorldVoxel { x, y, z, i: voxel.i }
+        })
+    }
+}
+
+/// Positions a voxel of a model with the given size in the world, as described in
+/// `Model::world_voxels`.
+pub fn world_position(size: Size, transform: &Transform, position: [i32; 3]) -> [i32; 3] {
+    let size = [size.x as i64, size.y as i64, size.z as i64];
+    // Work in doubled coordinates relative to the model's centre, so that the centre of models
+    // with an even size lands on a whole number
+    let doubled = [
+        2 * position[0] as i64 + 1 - size[0],
+        2 * position[1] as i64 + 1 - size[1],
+        2 * position[2] as i64 + 1 - size[2],
+    ];
+    let mut world = [0; 3];
+    for (axis, row) in transform.r.iter().enumerate() {
+        let rotated = row[0] as i64 * doubled[0] + row[1] as i64 * doubled[1] + row[2] as i64 * doubled[2];
+        // The extent of the model along this world axis, which is the size along whichever model
+        // axis has been rotated onto it
+        let extent = row.iter().zip(&size).map(|(&r, &s)| if r == 0 { 0 } else { s }).sum::<i64>();
+        world[axis] = (transform.t[axis] as i64 + (rotated + extent % 2 - 1).div_euclid(2)) as i32;
+    }
+    world
+}
+
 named!(pub parse_size <CompleteByteSlice, Size>, do_parse!(
   x: le_u32 >>
   y: le_u32 >>
@@ -57,7 +164,7 @@ named!(parse_voxel <CompleteByteSlice, Voxel>, do_parse!(
 ));
 
 named!(pub parse_voxels <CompleteByteSlice, Vec<Voxel> >, do_parse!(
-  num_voxels: le_u32 >>
-  voxels: many_m_n!(num_voxels as usize, num_voxels as usize, parse_voxel) >>
+  num_voxels: call!(le_count, 4) >>
+  voxels: many_m_n!(num_voxels, num_voxels, parse_voxel) >>
   (voxels)
 ));
\ No newline at end of file
@@ -1,156 +1,429 @@
-use {DEFAULT_PALETTE, DotVoxData, Model, model, palette, Size, Voxel, scene};
-use nom::IResult;
+use {camera, Camera, DEFAULT_PALETTE, DotVoxData, material, Material, Model, model, palette, Size, ValidationIssue, Voxel, scene};
+use byteorder::{ByteOrder, LittleEndian};
+use error::ParseError;
+use nom::{ErrorKind, IResult};
 use nom::types::CompleteByteSlice;
-use std::collections::HashMap;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+// Hash maps need a source of randomness, which only `std` provides
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+#[cfg(not(feature = "std"))]
+use std::collections::BTreeMap as Map;
 use std::str;
-use std::str::Utf8Error;
-use scene::{Node, SceneGraph};
+use scene::{Layer, Node, SceneGraph};
 
-const MAGIC_NUMBER: &'static str = "VOX ";
+pub const MAGIC_NUMBER: &str = "VOX ";
+
+/// The size of the ID and the two size fields at the start of every chunk
+const CHUNK_HEADER_SIZE: usize = 12;
 
 #[derive(Debug, PartialEq)]
 pub enum Chunk {
     Main(Vec<Chunk>),
     Size(Size),
     Voxels(Vec<Voxel>),
-    Pack(Model),
+    Pack(u32),
     Palette(Vec<u32>),
+    PaletteNotes(Vec<String>),
+    IndexMap([u8; 256]),
     Material(Material),
     SceneNode(Node),
-    Unknown(String),
+    Layer(Layer),
+    RenderObject(Dict),
+    Camera(Camera),
+    Unknown(RawChunk),
     Invalid(Vec<u8>),
 }
 
-/// A material used to render this model.
+/// A chunk which this crate doesn't recognise or couldn't parse, kept so that it isn't lost when
+/// the data is written back out
 #[derive(Clone, Debug, PartialEq)]
-pub struct Material {
-    /// The Material's ID
-    pub id: u32,
-    /// Properties of the material, mapped by property name.
-    pub properties: Dict,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RawChunk {
+    /// The four character ID of the chunk
+    pub id: String,
+    /// The content of the chunk
+    pub content: Vec<u8>,
+    /// The chunk's children, still encoded
+    pub children: Vec<u8>,
+    /// The index of the chunk among the children of its parent chunk.
+    ///
+    /// When writing `DotVoxData::unknown_chunks`, each chunk is placed after this many of the
+    /// other chunks within the MAIN chunk, so that it keeps its place among the chunks around it
+    /// when a file is written back. Chunks positioned past the end are written last.
+    pub position: usize,
+}
+
+impl RawChunk {
+    /// Parses the chunk's children, without interpreting them.
+    ///
+    /// # Errors
+    /// If the children are not a sequence of complete chunks. The offsets of any error are from
+    /// the start of `children`.
+    pub fn parse_children(&self) -> Result<Vec<RawChunk>, ParseError> {
+        let children = read_chunks(&self.children, 0, Some(&self.id))?;
+        Ok(children.into_iter().enumerate().map(|(position, chunk)| chunk.to_raw(position)).collect())
+    }
+}
+
+/// A chunk found within the input, with its content and children not yet parsed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LocatedChunk<'a> {
+    pub id: &'a str,
+    /// The offset of the start of the chunk from the start of the input
+    pub offset: usize,
+    pub content: &'a [u8],
+    pub children: &'a [u8],
 }
 
-/// General dictionary
-pub type Dict = HashMap<String, String>;
+impl<'a> LocatedChunk<'a> {
+    /// Finds the chunks within this chunk's children.
+    pub fn read_children(&self) -> Result<Vec<LocatedChunk<'a>>, ParseError> {
+        let offset = self.offset + CHUNK_HEADER_SIZE + self.content.len();
+        read_chunks(self.children, offset, Some(self.id))
+    }
+
+    pub fn to_raw(self, position: usize) -> RawChunk {
+        RawChunk {
+            id: self.id.to_owned(),
+            content: self.content.to_vec(),
+            children: self.children.to_vec(),
+            position,
+        }
+    }
+}
+
+/// Finds the version and MAIN chunk of a .vox file.
+pub fn read_vox_file(input: &[u8]) -> Result<(u32, LocatedChunk<'_>), ParseError> {
+    if !input.starts_with(MAGIC_NUMBER.as_bytes()) {
+        return Err(ParseError::new(None, 0, format!("expected the magic number {:?}", MAGIC_NUMBER)));
+    }
+    if input.len() < 8 {
+        return Err(ParseError::new(None, 4, "expected a version number"));
+    }
+    let version = LittleEndian::read_u32(&input[4..8]);
+    let (main, _) = read_chunk(&input[8..], 8, None)?;
+    Ok((version, main))
+}
+
+/// Finds the chunk at the start of `input`, which is at `offset` from the start of the whole
+/// input, returning it and the input following it. `parent` is the ID of the chunk containing
+/// the input, if any.
+pub fn read_chunk<'a>(input: &'a [u8], offset: usize, parent: Option<&str>)
+                      -> Result<(LocatedChunk<'a>, &'a [u8]), ParseError> {
+    if input.len() < CHUNK_HEADER_SIZE {
+        let message = match parent {
+            Some(parent) => format!("expected a chunk header, but only {} bytes remain in the {} chunk",
+                                    input.len(), parent),
+            None => format!("expected a chunk header, but only {} bytes remain", input.len()),
+        };
+        return Err(ParseError::new(None, offset, message));
+    }
+    let id = str::from_utf8(&input[..4])
+        .map_err(|_| ParseError::new(None, offset, format!("chunk ID {:?} is not valid UTF-8", &input[..4])))?;
+    let content_size = LittleEndian::read_u32(&input[4..8]) as usize;
+    let children_size = LittleEndian::read_u32(&input[8..12]) as usize;
+
+    let rest = &input[CHUNK_HEADER_SIZE..];
+    if content_size > rest.len() {
+        let message = format!("declared {} bytes of content, but only {} remain", content_size, rest.len());
+        return Err(ParseError::new(Some(id), offset, message));
+    }
+    let (content, rest) = rest.split_at(content_size);
+    if children_size > rest.len() {
+        let message = format!("declared {} bytes of children, but only {} remain", children_size, rest.len());
+        return Err(ParseError::new(Some(id), offset, message));
+    }
+    let (children, rest) = rest.split_at(children_size);
+    Ok((LocatedChunk { id, offset, content, children }, rest))
+}
+
+/// Finds every chunk in `input`, as in `read_chunk`.
+pub fn read_chunks<'a>(mut input: &'a [u8], mut offset: usize, parent: Option<&str>)
+                       -> Result<Vec<LocatedChunk<'a>>, ParseError> {
+    let mut chunks = vec![];
+    while !input.is_empty() {
+        let (chunk, rest) = read_chunk(input, offset, parent)?;
+        offset += input.len() - rest.len();
+        input = rest;
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+/// Checks that the content of the chunks making up models is complete. Other chunks which can't
+/// be parsed are skipped, but a model missing from the file would change the ID of every later
+/// model.
+pub fn check_content(chunk: &LocatedChunk) -> Result<(), ParseError> {
+    if !chunk.children.is_empty() {
+        return Ok(());
+    }
+    let length = chunk.content.len();
+    let error = |message: String| Err(ParseError::new(Some(chunk.id), chunk.offset, message));
+    match chunk.id {
+        "SIZE" if length < 12 => error(format!("expected 12 bytes of content, but found {}", length)),
+        "PACK" if length < 4 => error(format!("expected a model count, but found {} bytes of content", length)),
+        "XYZI" if length < 4 => error(format!("expected a voxel count, but found {} bytes of content", length)),
+        "XYZI" => {
+            let declared = LittleEndian::read_u32(chunk.content) as usize;
+            let remaining = (length - 4) / 4;
+            if declared > remaining {
+                error(format!("declared {} voxels, but only {} remain in chunk", declared, remaining))
+            } else {
+                Ok(())
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// General dictionary. This is a `BTreeMap` rather than a `HashMap` when built without `std`.
+pub type Dict = Map<String, String>;
 
 /// Recognizes an unsigned 1 byte integer (equivalent to take!(1)
 #[inline]
 pub fn le_u8(i: CompleteByteSlice) -> IResult<CompleteByteSlice, u8> {
-    Ok((CompleteByteSlice(&i[1..]), i[0]))
+    match i.first() {
+        Some(&res) => Ok((CompleteByteSlice(&i[1..]), res)),
+        None => Err(nom::Err::Error(error_position!(i, ErrorKind::Eof))),
+    }
 }
 
 /// Recognizes little endian unsigned 4 bytes integer
 #[inline]
 pub fn le_u32(i: CompleteByteSlice) -> IResult<CompleteByteSlice, u32> {
-    let res = ((i[3] as u32) << 24) + ((i[2] as u32) << 16) + ((i[1] as u32) << 8) + i[0] as u32;
-    Ok((CompleteByteSlice(&i[4..]), res))
+    if i.len() < 4 {
+        return Err(nom::Err::Error(error_position!(i, ErrorKind::Eof)));
+    }
+    Ok((CompleteByteSlice(&i[4..]), LittleEndian::read_u32(&i)))
 }
 
-pub fn to_str(i: CompleteByteSlice) -> Result<String, Utf8Error> {
-    let res = str::from_utf8(i.0)?;
-    Ok(res.to_owned())
+/// Recognizes a little endian unsigned 4 byte count of items which are each at least `min_size`
+/// bytes long, failing if that many items couldn't fit in the rest of the input. Counts are
+/// checked before being passed to `many_m_n!`, which allocates space for them all up front, so
+/// that a corrupt count can't cause a huge allocation.
+pub fn le_count(i: CompleteByteSlice, min_size: usize) -> IResult<CompleteByteSlice, usize> {
+    let (rest, count) = le_u32(i)?;
+    if count as usize > rest.len() / min_size {
+        return Err(nom::Err::Error(error_position!(i, ErrorKind::TooLarge)));
+    }
+    Ok((rest, count as usize))
 }
 
-named!(pub parse_vox_file <CompleteByteSlice, DotVoxData>, do_parse!(
-  tag!(MAGIC_NUMBER) >>
-  version: le_u32 >>
-  main: parse_chunk >>
-  (map_chunk_to_data(version, main))
-));
+/// MagicaVoxel doesn't guarantee that user supplied strings (such as node names) are valid UTF-8,
+/// so rather than failing the whole chunk we replace any invalid sequences.
+pub fn to_str_lossy(i: CompleteByteSlice) -> String {
+    String::from_utf8_lossy(i.0).into_owned()
+}
+
+pub fn parse_vox_file(input: &[u8]) -> Result<DotVoxData, ParseError> {
+    let (version, main) = read_vox_file(input)?;
+    if main.id != "MAIN" {
+        debug!("Expected MAIN chunk, got {:?}", main.id);
+        return Ok(map_chunk_to_data(version, Chunk::Unknown(main.to_raw(0))));
+    }
+    let chunks = main.read_children()?;
+    for chunk in &chunks {
+        check_content(chunk)?;
+    }
+    Ok(map_chunk_to_data(version, Chunk::Main(build_chunks(&chunks))))
+}
 
-fn map_chunk_to_data(version: u32, main: Chunk) -> DotVoxData {
+fn build_located_chunk(position: usize, chunk: &LocatedChunk) -> Chunk {
+    let built = build_chunk(
+        chunk.id.to_owned(),
+        CompleteByteSlice(chunk.content),
+        chunk.children.len() as u32,
+        CompleteByteSlice(chunk.children),
+    );
+    place_chunk(built, position)
+}
+
+/// Records the index of an unknown chunk among the children of the MAIN chunk, as chunks are
+/// built without knowing their siblings.
+pub fn place_chunk(chunk: Chunk, position: usize) -> Chunk {
+    match chunk {
+        Chunk::Unknown(chunk) => Chunk::Unknown(RawChunk { position, ..chunk }),
+        chunk => chunk,
+    }
+}
+
+/// Builds each of the children of the MAIN chunk, in the order they appear in the file.
+#[cfg(not(feature = "rayon"))]
+fn build_chunks(chunks: &[LocatedChunk]) -> Vec<Chunk> {
+    chunks.iter().enumerate().map(|(position, chunk)| build_located_chunk(position, chunk)).collect()
+}
+
+/// Builds each of the children of the MAIN chunk, in the order they appear in the file.
+///
+/// The extents of every chunk are already known, so their content (which is mostly voxels, in
+/// large files) is decoded in parallel. Anything which would make the file fail to parse has
+/// already been checked, so errors are still reported for the first bad chunk.
+#[cfg(feature = "rayon")]
+fn build_chunks(chunks: &[LocatedChunk]) -> Vec<Chunk> {
+    chunks.par_iter().enumerate().map(|(position, chunk)| build_located_chunk(position, chunk)).collect()
+}
+
+pub fn map_chunk_to_data(version: u32, main: Chunk) -> DotVoxData {
     match main {
         Chunk::Main(children) => {
             let mut size_holder: Option<Size> = None;
             let mut models: Vec<Model> = vec![];
             let mut palette_holder: Vec<u32> = DEFAULT_PALETTE.to_vec();
+            let mut palette_notes: Vec<String> = vec![];
+            let mut index_map: Option<[u8; 256]> = None;
             let mut materials: Vec<Material> = vec![];
             let mut scene_graph = SceneGraph::new();
+            let mut layers: Vec<Layer> = vec![];
+            let mut render_objects: Vec<Dict> = vec![];
+            let mut cameras: Vec<Camera> = vec![];
+            let mut unknown_chunks: Vec<RawChunk> = vec![];
+            let mut model_issues: Vec<ValidationIssue> = vec![];
+            let mut declared_models: Option<u32> = None;
             for chunk in children {
+                // Each model is a SIZE chunk immediately followed by an XYZI chunk. Where either
+                // is missing an empty model takes its place, so that later models keep their IDs.
+                let chunk = match (size_holder.take(), chunk) {
+                    (Some(size), Chunk::Voxels(voxels)) => {
+                        models.push(Model { size, voxels });
+                        continue;
+                    }
+                    (Some(size), chunk) => {
+                        model_issues.push(ValidationIssue::MissingVoxels { model: models.len() });
+                        models.push(Model { size, voxels: vec![] });
+                        chunk
+                    }
+                    (None, chunk) => chunk,
+                };
                 match chunk {
                     Chunk::Size(size) => size_holder = Some(size),
-                    Chunk::Voxels(voxels) => {
-                        if let Some(size) = size_holder {
-                            models.push(Model { size, voxels })
-                        }
+                    Chunk::Voxels(_) => {
+                        model_issues.push(ValidationIssue::MissingSize { model: models.len() });
+                        models.push(Model { size: Size { x: 0, y: 0, z: 0 }, voxels: vec![] });
+                    }
+                    Chunk::Pack(count) => {
+                        declared_models.get_or_insert(count);
                     }
-                    Chunk::Pack(model) => models.push(model),
                     Chunk::Palette(palette) => palette_holder = palette,
+                    Chunk::PaletteNotes(notes) => palette_notes = notes,
+                    Chunk::IndexMap(map) => index_map = Some(map),
                     Chunk::Material(material) => materials.push(material),
                     Chunk::SceneNode(node) => scene_graph.add_node(node),
+                    Chunk::Layer(layer) => layers.push(layer),
+                    Chunk::RenderObject(render_object) => render_objects.push(render_object),
+                    Chunk::Camera(camera) => cameras.push(camera),
+                    Chunk::Unknown(chunk) => unknown_chunks.push(chunk),
                     _ => debug!("Unmapped chunk {:?}", chunk)
                 }
             }
+            if let Some(size) = size_holder {
+                model_issues.push(ValidationIssue::MissingVoxels { model: models.len() });
+                models.push(Model { size, voxels: vec![] });
+            }
+            match declared_models {
+                Some(declared) if declared as usize != models.len() => {
+                    model_issues.push(ValidationIssue::ModelCountMismatch { declared, found: models.len() });
+                }
+                _ => (),
+            }
 
             DotVoxData {
                 version,
                 models,
                 palette: palette_holder,
+                palette_notes,
+                index_map,
                 materials,
                 scene: scene_graph.collapse_to_vec(),
+                scene_graph,
+                layers,
+                render_objects,
+                cameras,
+                unknown_chunks,
+                model_issues,
             }
         }
         _ => DotVoxData {
             version,
             models: vec![],
             palette: vec![],
+            palette_notes: vec![],
+            index_map: None,
             materials: vec![],
             scene: vec![],
+            scene_graph: SceneGraph::new(),
+            layers: vec![],
+            render_objects: vec![],
+            cameras: vec![],
+            unknown_chunks: vec![],
+            model_issues: vec![],
         }
     }
 }
 
-named!(parse_chunk <CompleteByteSlice, Chunk>, do_parse!(
-    id: map_res!(take!(4), to_str) >>
-    content_size: le_u32 >>
-    children_size: le_u32 >>
-    chunk_content: take!(content_size) >>
-    child_content: take!(children_size) >>
-    (build_chunk(id, chunk_content, children_size, child_content))
-));
-
-fn build_chunk(string: String,
-               chunk_content: CompleteByteSlice,
-               children_size: u32,
-               child_content: CompleteByteSlice) -> Chunk {
+pub fn build_chunk(string: String,
+                   chunk_content: CompleteByteSlice,
+                   children_size: u32,
+                   child_content: CompleteByteSlice) -> Chunk {
     let id = string.as_str();
     if children_size == 0 {
-        match id {
+        let chunk = match id {
             "SIZE" => build_size_chunk(chunk_content),
             "XYZI" => build_voxel_chunk(chunk_content),
             "PACK" => build_pack_chunk(chunk_content),
             "RGBA" => build_palette_chunk(chunk_content),
+            "NOTE" => build_notes_chunk(chunk_content),
+            "IMAP" => build_index_map_chunk(chunk_content),
             "MATL" => build_material_chunk(chunk_content),
+            "MATT" => build_legacy_material_chunk(chunk_content),
             "nGRP" => build_group_node_chunk(chunk_content),
             "nTRN" => build_transform_node_chunk(chunk_content),
             "nSHP" => build_shape_node_chunk(chunk_content),
+            "LAYR" => build_layer_chunk(chunk_content),
+            "rOBJ" => build_render_object_chunk(chunk_content),
+            "rCAM" => build_camera_chunk(chunk_content),
             _ => {
                 debug!("Unknown childless chunk {:?}", id);
-                Chunk::Unknown(id.to_owned())
-            }
-        }
-    } else {
-        let result: IResult<CompleteByteSlice, Vec<Chunk>> = many0!(child_content, parse_chunk);
-        let child_chunks = match result {
-            Ok((_, result)) => result,
-            result => {
-                debug!("Failed to parse child chunks, due to {:?}", result);
-                vec![]
+                Chunk::Invalid(chunk_content.to_vec())
             }
         };
-        match id {
-            "MAIN" => Chunk::Main(child_chunks),
-            "PACK" => build_pack_chunk(chunk_content),
-            _ => {
-                debug!("Unknown chunk with children {:?}", id);
-                Chunk::Unknown(id.to_owned())
-            }
+        match chunk {
+            // Chunks which couldn't be parsed are kept as they are, like unknown chunks, so that
+            // writing the data back doesn't lose them or move the chunks after them
+            Chunk::Invalid(content) => Chunk::Unknown(RawChunk {
+                id: string,
+                content,
+                children: vec![],
+                position: 0,
+            }),
+            chunk => chunk,
         }
+    } else {
+        // Children aren't parsed here, as only the MAIN chunk (which is handled by
+        // `parse_vox_file`) has children this crate understands, and recursing into them would let
+        // deeply nested chunks overflow the stack.
+        debug!("Unknown chunk with children {:?}", id);
+        Chunk::Unknown(RawChunk {
+            id: id.to_owned(),
+            content: chunk_content.to_vec(),
+            children: child_content.to_vec(),
+            position: 0,
+        })
     }
 }
 
 fn build_material_chunk(chunk_content: CompleteByteSlice) -> Chunk {
-    if let Ok((_, material)) = parse_material(chunk_content) {
+    if let Ok((_, material)) = material::parse_material(chunk_content) {
+        return Chunk::Material(material);
+    }
+    Chunk::Invalid(chunk_content.to_vec())
+}
+
+fn build_legacy_material_chunk(chunk_content: CompleteByteSlice) -> Chunk {
+    if let Ok((_, material)) = material::parse_legacy_material(chunk_content) {
         return Chunk::Material(material);
     }
     Chunk::Invalid(chunk_content.to_vec())
@@ -163,15 +436,27 @@ fn build_palette_chunk(chunk_content: CompleteByteSlice) -> Chunk {
     Chunk::Invalid(chunk_content.to_vec())
 }
 
-fn build_pack_chunk(chunk_content: CompleteByteSlice) -> Chunk {
-    if let Ok((chunk_content, Chunk::Size(size))) = parse_chunk(chunk_content) {
-        if let Ok((_, Chunk::Voxels(voxels))) = parse_chunk(chunk_content) {
-            return Chunk::Pack(Model { size, voxels: voxels.to_vec() });
-        }
+fn build_notes_chunk(chunk_content: CompleteByteSlice) -> Chunk {
+    if let Ok((_, notes)) = palette::extract_notes(chunk_content) {
+        return Chunk::PaletteNotes(notes);
+    }
+    Chunk::Invalid(chunk_content.to_vec())
+}
+
+fn build_index_map_chunk(chunk_content: CompleteByteSlice) -> Chunk {
+    if let Ok((_, index_map)) = palette::extract_index_map(chunk_content) {
+        return Chunk::IndexMap(index_map);
     }
     Chunk::Invalid(chunk_content.to_vec())
 }
 
+fn build_pack_chunk(chunk_content: CompleteByteSlice) -> Chunk {
+    match le_u32(chunk_content) {
+        Ok((_, model_count)) => Chunk::Pack(model_count),
+        _ => Chunk::Invalid(chunk_content.to_vec())
+    }
+}
+
 fn build_size_chunk(chunk_content: CompleteByteSlice) -> Chunk {
     match model::parse_size(chunk_content) {
         Ok((_, size)) => Chunk::Size(size),
@@ -207,33 +492,45 @@ fn build_shape_node_chunk(chunk_content: CompleteByteSlice) -> Chunk {
     }
 }
 
-named!(pub parse_material <CompleteByteSlice, Material>, do_parse!(
-    id: le_u32 >>
-    properties: parse_dict >>
-    (Material { id, properties })
-));
+fn build_layer_chunk(chunk_content: CompleteByteSlice) -> Chunk {
+    match scene::parse_layer_node(chunk_content) {
+        Ok((_, layer)) => Chunk::Layer(layer),
+        _ => Chunk::Invalid(chunk_content.to_vec()),
+    }
+}
 
+fn build_render_object_chunk(chunk_content: CompleteByteSlice) -> Chunk {
+    match parse_render_object(chunk_content) {
+        Ok((_, render_object)) => Chunk::RenderObject(render_object),
+        _ => Chunk::Invalid(chunk_content.to_vec()),
+    }
+}
+
+fn build_camera_chunk(chunk_content: CompleteByteSlice) -> Chunk {
+    match camera::parse_camera(chunk_content) {
+        Ok((_, camera)) => Chunk::Camera(camera),
+        _ => Chunk::Invalid(chunk_content.to_vec()),
+    }
+}
+
+named!(pub parse_render_object <CompleteByteSlice, Dict>, call!(parse_dict));
 
 named!(pub parse_dict <CompleteByteSlice, Dict>, do_parse!(
-    count: le_u32 >>
-    entries: many_m_n!(count as usize, count as usize, parse_dict_entry) >>
+    count: call!(le_count, 8) >>
+    entries: many_m_n!(count, count, parse_dict_entry) >>
     (build_dict_from_entries(entries))
 ));
 
 named!(parse_dict_entry <CompleteByteSlice, (String, String)>, tuple!(parse_string, parse_string));
 
-named!(parse_string <CompleteByteSlice, String>, do_parse!(
+named!(pub parse_string <CompleteByteSlice, String>, do_parse!(
     count: le_u32 >>
-    buffer: map_res!(take!(count), to_str) >>
+    buffer: map!(take!(count), to_str_lossy) >>
     (buffer)
 ));
 
 fn build_dict_from_entries(entries: Vec<(String, String)>) -> Dict {
-    let mut map = HashMap::with_capacity(entries.len());
-    for (key, value) in entries {
-        map.insert(key, value);
-    }
-    map
+    entries.into_iter().collect()
 }
 
 #[cfg(test)]
@@ -241,6 +538,15 @@ mod tests {
     use avow::vec;
     use super::*;
 
+named!(parse_chunk <CompleteByteSlice, Chunk>, do_parse!(
+    id: map!(take!(4), to_str_lossy) >>
+    content_size: le_u32 >>
+    children_size: le_u32 >>
+    chunk_content: take!(content_size) >>
+    child_content: take!(children_size) >>
+    (build_chunk(id, chunk_content, children_size, child_content))
+));
+
     #[test]
     fn can_parse_size_chunk() {
         let bytes = include_bytes!("resources/valid_size.bytes").to_vec();
@@ -276,6 +582,24 @@ mod tests {
         };
     }
 
+    #[test]
+    fn can_parse_palette_notes_chunk() {
+        let bytes = include_bytes!("resources/valid_notes.bytes").to_vec();
+        let result = parse_chunk(CompleteByteSlice(&bytes));
+        assert!(result.is_ok());
+        let (_, notes) = result.unwrap();
+        match notes {
+            Chunk::PaletteNotes(notes) => {
+                assert_eq!(notes.len(), 32);
+                assert_eq!(notes[0], "glass");
+                assert_eq!(notes[1], "");
+                assert_eq!(notes[3], "emissive");
+                assert!(notes[31].starts_with("water "));
+            }
+            chunk => panic!("Expecting PaletteNotes chunk, got {:?}", chunk)
+        };
+    }
+
     #[test]
     fn can_parse_palette_chunk() {
         let bytes = include_bytes!("resources/valid_palette.bytes").to_vec();
@@ -289,19 +613,176 @@ mod tests {
     }
 
     #[test]
-    fn can_parse_a_material_chunk() {
-        let bytes = include_bytes!("resources/valid_material.bytes").to_vec();
-        let result = parse_material(CompleteByteSlice(&bytes));
+    fn can_parse_a_transform_node_chunk_with_a_name() {
+        let bytes = include_bytes!("resources/valid_named_transform.bytes").to_vec();
+        let result = parse_chunk(CompleteByteSlice(&bytes));
         match result {
-            Ok((_, material)) => {
-                assert_eq!(material.id, 0);
-                assert_eq!(material.properties.get("_type"), Some(&"_diffuse".to_owned()));
-                assert_eq!(material.properties.get("_weight"), Some(&"1".to_owned()));
-                assert_eq!(material.properties.get("_rough"), Some(&"0.1".to_owned()));
-                assert_eq!(material.properties.get("_spec"), Some(&"0.5".to_owned()));
-                assert_eq!(material.properties.get("_ior"), Some(&"0.3".to_owned()));
+            Ok((_, Chunk::SceneNode(node))) => {
+                assert_eq!(node.id, 2);
+                assert_eq!(node.name(), Some("left arm – Öl"));
+                assert!(node.hidden());
             }
+            _ => panic!("Expected SceneNode chunk, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_in_dict_strings_is_replaced() {
+        let bytes = [1, 0, 0, 0, 5, 0, 0, 0, b'_', b'n', b'a', b'm', b'e', 3, 0, 0, 0, b'a', 0xE4, b'b'];
+        let result = parse_dict(CompleteByteSlice(&bytes));
+        match result {
+            Ok((_, dict)) => assert_eq!(dict.get("_name"), Some(&"a\u{FFFD}b".to_owned())),
             _ => panic!("Expected Done, got {:?}", result)
         }
     }
+
+    fn chunk(id: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(content);
+        bytes
+    }
+
+    fn model(x: u8, voxel_count: u8) -> Vec<u8> {
+        let mut bytes = chunk(b"SIZE", &[x, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        let mut voxels = vec![voxel_count, 0, 0, 0];
+        for i in 0..voxel_count {
+            voxels.extend_from_slice(&[i, 0, 0, 1]);
+        }
+        bytes.extend(chunk(b"XYZI", &voxels));
+        bytes
+    }
+
+    fn vox_file(children: &[u8]) -> Vec<u8> {
+        let mut bytes = b"VOX \x96\0\0\0MAIN\0\0\0\0".to_vec();
+        bytes.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(children);
+        bytes
+    }
+
+    fn model_error(children: &[u8]) -> ParseError {
+        let bytes = vox_file(children);
+        let error = parse_vox_file(&bytes).unwrap_err();
+        assert_eq!(::borrowed::parse_vox_file(&bytes).unwrap_err(), error);
+        error
+    }
+
+    #[test]
+    fn models_are_kept_in_file_order_with_a_pack_chunk() {
+        let mut children = chunk(b"PACK", &[2, 0, 0, 0]);
+        children.extend(model(3, 1));
+        children.extend(chunk(b"xTRA", &[]));
+        children.extend(model(4, 2));
+        let data = parse_vox_file(&vox_file(&children)).unwrap();
+        let sizes: Vec<u32> = data.models.iter().map(|model| model.size.x).collect();
+        assert_eq!(sizes, vec![3, 4]);
+        assert_eq!(data.models[1].voxels.len(), 2);
+        assert_eq!(data.unknown_chunks.len(), 1);
+    }
+
+    /// Parses a file with the given children, checking that the borrowing parser agrees
+    fn recovered(children: &[u8]) -> DotVoxData {
+        let bytes = vox_file(children);
+        let data = parse_vox_file(&bytes).unwrap();
+        assert_eq!(::borrowed::parse_vox_file(&bytes).unwrap().to_owned(), data);
+        data
+    }
+
+    fn sizes(data: &DotVoxData) -> Vec<u32> {
+        data.models.iter().map(|model| model.size.x).collect()
+    }
+
+    #[test]
+    fn pack_chunks_declaring_the_wrong_number_of_models_are_reported() {
+        let mut children = chunk(b"PACK", &[3, 0, 0, 0]);
+        children.extend(model(3, 1));
+        children.extend(model(4, 2));
+        let data = recovered(&children);
+        assert_eq!(sizes(&data), vec![3, 4]);
+        assert_eq!(data.model_issues, vec![ValidationIssue::ModelCountMismatch { declared: 3, found: 2 }]);
+        assert_eq!(model_error(&chunk(b"PACK", &[3])),
+                   ParseError::new(Some("PACK"), 20, "expected a model count, but found 1 bytes of content"));
+    }
+
+    #[test]
+    fn size_chunks_without_voxels_become_empty_models() {
+        let mut children = model(3, 1);
+        children.truncate(24);
+        children.extend(model(4, 2));
+        let data = recovered(&children);
+        assert_eq!(sizes(&data), vec![3, 4]);
+        assert!(data.models[0].voxels.is_empty());
+        assert_eq!(data.models[1].voxels.len(), 2);
+        assert_eq!(data.model_issues, vec![ValidationIssue::MissingVoxels { model: 0 }]);
+
+        let mut children = chunk(b"PACK", &[2, 0, 0, 0]);
+        children.extend(model(3, 1));
+        children.extend(chunk(b"SIZE", &[1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]));
+        let data = recovered(&children);
+        assert_eq!(sizes(&data), vec![3, 1]);
+        assert!(data.models[1].voxels.is_empty());
+        assert_eq!(data.model_issues, vec![ValidationIssue::MissingVoxels { model: 1 }]);
+    }
+
+    #[test]
+    fn voxel_chunks_without_sizes_become_empty_models() {
+        let mut children = model(3, 1);
+        children.extend(chunk(b"RGBA", &[0; 1024]));
+        children.extend(chunk(b"XYZI", &[1, 0, 0, 0, 0, 0, 0, 1]));
+        children.extend(model(4, 2));
+        let data = recovered(&children);
+        // The model after the stray voxels keeps its ID
+        assert_eq!(sizes(&data), vec![3, 0, 4]);
+        assert_eq!(data.models[1], Model { size: Size { x: 0, y: 0, z: 0 }, voxels: vec![] });
+        assert_eq!(data.models[2].voxels.len(), 2);
+        assert_eq!(data.model_issues, vec![ValidationIssue::MissingSize { model: 1 }]);
+    }
+
+    #[test]
+    fn many_models_keep_their_order_and_errors_point_at_the_bad_one() {
+        let models: Vec<Vec<u8>> = (0..500).map(|i| model(i as u8, (i % 7) as u8 + 1)).collect();
+        let data = parse_vox_file(&vox_file(&models.concat())).unwrap();
+        let sizes: Vec<u32> = data.models.iter().map(|model| model.size.x).collect();
+        assert_eq!(sizes, (0..500).map(|i| i % 256).collect::<Vec<u32>>());
+        assert!(data.models.iter().enumerate().all(|(i, model)| model.voxels.len() == i % 7 + 1));
+
+        let mut corrupt = models.clone();
+        corrupt[250][24 + 12] = 200;
+        let offset = 20 + models[..250].iter().map(Vec::len).sum::<usize>() + 24;
+        assert_eq!(model_error(&corrupt.concat()),
+                   ParseError::new(Some("XYZI"), offset, "declared 200 voxels, but only 6 remain in chunk"));
+    }
+
+    #[test]
+    fn short_input_is_an_error() {
+        assert!(le_u8(CompleteByteSlice(&[])).is_err());
+        assert!(le_u32(CompleteByteSlice(&[1, 2, 3])).is_err());
+        assert_eq!(le_u32(CompleteByteSlice(&[1, 2, 3, 4, 5])), Ok((CompleteByteSlice(&[5]), 0x0403_0201)));
+    }
+
+    #[test]
+    fn counts_larger_than_the_input_are_rejected_before_allocating() {
+        let bytes = [0xFF, 0xFF, 0xFF, 0xFF, 1, 0, 0, 0, 1, 0, 0, 0];
+        assert!(parse_dict(CompleteByteSlice(&bytes)).is_err());
+        assert!(model::parse_voxels(CompleteByteSlice(&bytes)).is_err());
+        assert!(palette::extract_notes(CompleteByteSlice(&bytes)).is_err());
+        let bytes = [2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0];
+        assert_eq!(le_count(CompleteByteSlice(&bytes), 4), Ok((CompleteByteSlice(&bytes[4..]), 2)));
+        assert!(le_count(CompleteByteSlice(&bytes), 8).is_err());
+    }
+
+    #[test]
+    fn deeply_nested_chunks_do_not_overflow_the_stack() {
+        // Each chunk's only child is the next chunk
+        let depth = 100_000;
+        let mut nested = vec![];
+        for level in 1..=depth {
+            nested.extend_from_slice(b"xNST\0\0\0\0");
+            nested.extend_from_slice(&((depth - level) * 12u32).to_le_bytes());
+        }
+        let data = parse_vox_file(&vox_file(&nested)).unwrap();
+        assert_eq!(data.unknown_chunks.len(), 1);
+        assert_eq!(data.unknown_chunks[0].children.len(), nested.len() - 12);
+    }
 }
@@ -1,6 +1,14 @@
 use nom::types::CompleteByteSlice;
-use ::parser::{le_u32, parse_dict, Dict};
-use std::collections::HashMap;
+use ::parser::{le_count, le_u32, parse_dict, Dict};
+#[cfg(not(feature = "std"))]
+use prelude::*;
+#[cfg(feature = "std")]
+use std::collections::{HashMap as Map, HashSet as Set};
+#[cfg(not(feature = "std"))]
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
 
 /*
 (1) Transform Node Chunk : "nTRN"
@@ -12,13 +20,14 @@ DICT	: node attributes
 int32 	: child node id
 int32 	: reserved id (must be -1)
 int32	: layer id
-int32	: num of frames (must be 1)
+int32	: num of frames
 
 // for each frame
 {
 DICT	: frame attributes
 	  (_r : int8) ROTATION, see (c)
 	  (_t : int32x3) translation
+	  (_f : int32) frame index, start from 0
 }xN
 
 =================================
@@ -45,47 +54,165 @@ int32 	: num of models (must be 1)
 int32	: model id
 DICT	: model attributes : reserved
 }xN
+
+=================================
+(4) Layer Chunk : "LAYR"
+
+int32	: layer id
+DICT	: layer attributes
+	  (_name : string)
+	  (_hidden : 0/1)
+int32	: reserved id, must be -1
 */
 
-#[derive(Debug, PartialEq)]
+/// A node in the scene graph
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Node {
+    /// The node's ID, unique within the scene graph.
     pub id: u32,
+    /// Attributes of the node, mapped by attribute name (e.g. `_name`, `_hidden`).
+    pub attributes: Dict,
+    /// What kind of node this is, along with its kind-specific data.
     pub kind: NodeKind,
 }
+impl Node {
+    /// The name given to this node in MagicaVoxel, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.attributes.get("_name").map(String::as_str)
+    }
+    /// Whether this node has been hidden in MagicaVoxel.
+    pub fn hidden(&self) -> bool {
+        self.attributes.get("_hidden").is_some_and(|hidden| hidden == "1")
+    }
+}
 
-#[derive(Debug, PartialEq)]
+/// The different kinds of node which make up a scene graph
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum NodeKind {
+    /// A node grouping together a number of Transform nodes.
     Group {
+        /// The IDs of the nodes in this group.
         children_ids: Vec<u32>,
     },
+    /// A node positioning its child within its parent.
     Transform {
+        /// The ID of the node being transformed.
         child_id: u32,
-        transform: Transform,
+        /// The ID of the layer this node belongs to, or `u32::MAX` if it doesn't belong to one.
+        layer_id: u32,
+        /// The animation frames of the transformation to apply to the child.
+        frames: Vec<Frame>,
     },
-    Shape {  
+    /// A leaf node displaying a model.
+    Shape {
+        /// The index of the displayed model within `DotVoxData::models`.
         model_id: u32,
     }
 }
 
-/// TODO doc
+impl NodeKind {
+    /// The transformation of the first animation frame, if this is a Transform node.
+    ///
+    /// A Transform node without any frames is treated as the identity transformation.
+    pub fn transform(&self) -> Option<Transform> {
+        match self {
+            NodeKind::Transform { frames, .. } => Some(
+                frames.first().map(Frame::transform).unwrap_or_default()
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// A layer, as used to organise Transform nodes in MagicaVoxel
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Layer {
+    /// The layer's ID, as referenced by `NodeKind::Transform::layer_id`.
+    pub id: u32,
+    /// Attributes of the layer, mapped by attribute name (e.g. `_name`, `_hidden`).
+    pub attributes: Dict,
+}
+impl Layer {
+    /// The name given to this layer in MagicaVoxel, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.attributes.get("_name").map(String::as_str)
+    }
+    /// Whether this layer has been hidden in MagicaVoxel.
+    pub fn hidden(&self) -> bool {
+        self.attributes.get("_hidden").is_some_and(|hidden| hidden == "1")
+    }
+}
+
+/// A single animation frame of a Transform node
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Frame {
+    /// Attributes of the frame, mapped by attribute name (`_r` rotation, `_t` translation and
+    /// `_f` frame index).
+    pub attributes: Dict,
+}
+impl Frame {
+    /// The transformation stored in this frame.
+    pub fn transform(&self) -> Transform {
+        Transform::from_dict(&self.attributes)
+    }
+    /// The index of this frame within the animation, defaulting to 0 when not set.
+    pub fn index(&self) -> u32 {
+        self.attributes.get("_f").and_then(|f| f.parse().ok()).unwrap_or(0)
+    }
+}
+
+/// A rotation followed by a translation
+///
+/// A point `p` is transformed into `r * p + t`, treating `p` as a column vector.
+///
+/// With the `mint` or `glam` features, a transformation can be converted into a 4x4 matrix
+/// which does the same, with the translation in its last column. Composing transformations with
+/// `apply` corresponds to multiplying matrices in the opposite order, so
+/// `a.apply(b).to_mat4() == b.to_mat4() * a.to_mat4()`: a shape's world matrix is the matrix of
+/// the root Transform node times that of each descendant Transform node in turn.
+///
+/// These matrices don't include MagicaVoxel's centring of models on their translation. To place
+/// a model's voxels where `Model::world_voxels` does, treating the voxel at `p` as the unit cube
+/// from `p` to `p + 1`, subtract `size / 2` (without rounding) from each vertex, apply the
+/// matrix, and then add `0.5` along each world axis onto which an odd sized axis of the model
+/// has been rotated.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Transform {
     /// Translation
     pub t: [i32; 3],
     /// Row-major rotation matrix
     pub r: [[i8; 3]; 3],
 }
-impl Transform {
+impl Default for Transform {
+    /// The identity transformation.
     fn default() -> Self {
         Self {
             t: [0, 0, 0],
             r: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
         }
     }
-    fn apply(self, other: Self) -> Self {
-        let dot_i32 = |v1: [i32; 3], v2: [i32; 3] | v1[0] * v2[0] + v1[1] * v2[1] + v1[2] * v2[2];
-        let dot_i8 = |v1: [i8; 3], v2: [i8; 3] | v1[0] * v2[0] + v1[1] * v2[1] + v1[2] * v2[2];
-        let add = |v1: [i32; 3], v2: [i32; 3] | [v1[0] + v2[0], v1[1] + v2[1], v1[2] + v2[2]];
+}
+impl Transform {
+    /// Composes two transformations, returning one which applies `self` first and `other` second.
+    ///
+    /// In other words, `a.apply(b).transform_point(p) == b.transform_point(a.transform_point(p))`.
+    /// When collapsing a scene graph, a child's world transformation is therefore
+    /// `child.apply(parent)`. Translations wrap around rather than overflowing.
+    pub fn apply(self, other: Self) -> Self {
+        let dot_i32 = |v1: [i32; 3], v2: [i32; 3]| {
+            v1[0].wrapping_mul(v2[0]).wrapping_add(v1[1].wrapping_mul(v2[1])).wrapping_add(v1[2].wrapping_mul(v2[2]))
+        };
+        let dot_i8 = |v1: [i8; 3], v2: [i8; 3]| {
+            v1[0].wrapping_mul(v2[0]).wrapping_add(v1[1].wrapping_mul(v2[1])).wrapping_add(v1[2].wrapping_mul(v2[2]))
+        };
+        let add = |v1: [i32; 3], v2: [i32; 3]| {
+            [v1[0].wrapping_add(v2[0]), v1[1].wrapping_add(v2[1]), v1[2].wrapping_add(v2[2])]
+        };
         let row_i32 = |m: [[i8; 3]; 3], r: usize| [m[r][0] as i32, m[r][1] as i32, m[r][2] as i32];
         let col_i8 = |m: [[i8; 3]; 3], c| [m[0][c], m[1][c], m[2][c]];
         let mul_mv_i32 = |m: [[i8; 3]; 3], v: [i32; 3]| [
@@ -111,147 +238,1016 @@ impl Transform {
             r,
         }
     }
-    fn from_dict(dict: Dict) -> Self {
-        let t = dict.get("_t").and_then(|s| {
-            let values = s.split(' ').map(str::parse::<i32>).filter_map(Result::ok).collect::<Vec<_>>();
-            if values.len() == 3 {
-                Some([values[0], values[1], values[2]])
-            } else {
-                debug!("Unknown translation format: {}", s);
-                None
+    /// Transforms a point, such as a voxel position, by rotating it and then translating it.
+    /// The result wraps around rather than overflowing.
+    pub fn transform_point(&self, p: [i32; 3]) -> [i32; 3] {
+        let row = |r: [i8; 3]| {
+            (r[0] as i32).wrapping_mul(p[0])
+                .wrapping_add((r[1] as i32).wrapping_mul(p[1]))
+                .wrapping_add((r[2] as i32).wrapping_mul(p[2]))
+        };
+        [
+            row(self.r[0]).wrapping_add(self.t[0]),
+            row(self.r[1]).wrapping_add(self.t[1]),
+            row(self.r[2]).wrapping_add(self.t[2]),
+        ]
+    }
+    /// The transformation which undoes this one, so that `t.apply(t.inverse())` is the identity.
+    ///
+    /// This assumes the rotation is a signed permutation matrix, as every rotation which can be
+    /// stored in a .vox file is, so that its inverse is its transpose. Translations wrap around
+    /// rather than overflowing.
+    pub fn inverse(&self) -> Self {
+        let mut r = [[0; 3]; 3];
+        for (i, row) in r.iter_mut().enumerate() {
+            for (j, entry) in row.iter_mut().enumerate() {
+                *entry = self.r[j][i];
             }
-        }).unwrap_or([0; 3]);
+        }
+        let rotated = Transform { t: [0; 3], r }.transform_point(self.t);
+        Transform {
+            t: [rotated[0].wrapping_neg(), rotated[1].wrapping_neg(), rotated[2].wrapping_neg()],
+            r,
+        }
+    }
+    /// Reads a transformation from the attributes of a Transform node frame.
+    ///
+    /// Missing or malformed `_t` and `_r` attributes are treated as no translation and no
+    /// rotation respectively.
+    pub fn from_dict(dict: &Dict) -> Self {
+        let t = Self::translation_from_dict(dict).unwrap_or_else(|error| {
+            debug!("{}", error);
+            [0; 3]
+        });
+        let r = Self::rotation_from_dict(dict).unwrap_or_else(|error| {
+            debug!("{}", error);
+            [[1, 0, 0], [0, 1, 0], [0, 0, 1]]
+        });
+
+        Self {
+            t, r
+        }
+    }
+    /// Reads a transformation like `from_dict`, but fails on malformed `_t` or `_r` attributes
+    /// rather than ignoring them.
+    pub fn try_from_dict(dict: &Dict) -> Result<Self, ScanError> {
+        Ok(Self {
+            t: Self::translation_from_dict(dict)?,
+            r: Self::rotation_from_dict(dict)?,
+        })
+    }
+    fn translation_from_dict(dict: &Dict) -> Result<[i32; 3], ScanError> {
+        match dict.get("_t") {
+            Some(s) => {
+                let values = s.split(' ').map(str::parse::<i32>).collect::<Result<Vec<_>, _>>();
+                match values {
+                    Ok(ref values) if values.len() == 3 => Ok([values[0], values[1], values[2]]),
+                    _ => Err(ScanError::InvalidTranslation(s.clone())),
+                }
+            }
+            None => Ok([0; 3]),
+        }
+    }
+    fn rotation_from_dict(dict: &Dict) -> Result<[[i8; 3]; 3], ScanError> {
+        match dict.get("_r") {
+            Some(s) => s.parse::<u8>().ok()
+                .and_then(Self::rotation_from_byte)
+                .ok_or_else(|| ScanError::InvalidRotation(s.clone())),
+            None => Ok([[1, 0, 0], [0, 1, 0], [0, 0, 1]]),
+        }
+    }
+
+    /// Encodes this transformation as the attributes of a Transform node frame.
+    ///
+    /// The translation is stored under `_t` as `"x y z"`, and the rotation under `_r` in the
+    /// packed format described in `rotation_to_byte`. Returns `None` if the rotation can't be
+    /// represented in a .vox file.
+    pub fn to_dict(&self) -> Option<Dict> {
+        Self::rotation_to_byte(self.r).map(|r| {
+            let mut dict = Dict::new();
+            dict.insert("_t".to_owned(), format!("{} {} {}", self.t[0], self.t[1], self.t[2]));
+            dict.insert("_r".to_owned(), r.to_string());
+            dict
+        })
+    }
 
+    /// Decodes a rotation matrix from the packed byte format used by the `_r` frame attribute.
+    ///
+    /// Returns `None` if the byte doesn't describe a valid rotation, such as when two rows have
+    /// their non-zero entry in the same column.
+    pub fn rotation_from_byte(n: u8) -> Option<[[i8; 3]; 3]> {
         // 0-1 : 1 : index of the non-zero entry in the first row
         // 2-3 : 2 : index of the non-zero entry in the second row
         // 4   : 0 : the sign in the first row (0 : positive; 1 : negative)
         // 5   : 1 : the sign in the second row (0 : positive; 1 : negative)
         // 6   : 1 : the sign in the third row (0 : positive; 1 : negative)
-        let r = dict.get("_r").and_then(|s| {
-            s.parse::<u8>().ok().and_then(|n| {
-                let signs = [
-                    if n >> 4 & 1 == 0 { 1 } else { -1 },
-                    if n >> 5 & 1 == 0 { 1 } else { -1 },
-                    if n >> 6 & 1 == 0 { 1 } else { -1 },
-                ];
-                let rows = [
-                    [1, 0, 0],
-                    [0, 1, 0],
-                    [0, 0, 1],
-                ];
-                if n & 3 != 3 && n >> 2 != 3 {
-                    let r1 = rows[(n & 3) as usize];
-                    let r2 = rows[(n >> 2 & 3) as usize];
-                    let r3 = rows[(!(n | (n >> 2)) & 3) as usize];
-                    Some([
-                        [r1[0]*signs[0], r1[1]*signs[0], r1[2]*signs[0]],
-                        [r2[0]*signs[1], r2[1]*signs[1], r2[2]*signs[1]],
-                        [r3[0]*signs[2], r3[1]*signs[2], r3[2]*signs[2]],
-                    ])
-                } else {
-                    debug!("Unknown rotation format: {}", s);
-                    None
+        let first = (n & 3) as usize;
+        let second = (n >> 2 & 3) as usize;
+        if first == 3 || second == 3 || first == second || n >> 7 != 0 {
+            return None;
+        }
+        let third = 3 - first - second;
+
+        let mut r = [[0; 3]; 3];
+        for (row, &column) in [first, second, third].iter().enumerate() {
+            r[row][column] = if n >> (4 + row) & 1 == 0 { 1 } else { -1 };
+        }
+        Some(r)
+    }
+
+    /// Encodes a rotation matrix in the packed byte format used by the `_r` frame attribute.
+    ///
+    /// Only signed permutation matrices (exactly one entry of 1 or -1 in each row and column)
+    /// can be encoded; `None` is returned for anything else.
+    pub fn rotation_to_byte(r: [[i8; 3]; 3]) -> Option<u8> {
+        let mut columns = [0; 3];
+        let mut signs = [0; 3];
+        for (row, entries) in r.iter().enumerate() {
+            let mut non_zero = entries.iter().enumerate().filter(|&(_, &entry)| entry != 0);
+            match (non_zero.next(), non_zero.next()) {
+                (Some((column, &entry)), None) if entry == 1 || entry == -1 => {
+                    columns[row] = column as u8;
+                    signs[row] = if entry == 1 { 0 } else { 1 };
                 }
-            })
-        }).unwrap_or([[1, 0, 0], [0, 1, 0], [0, 0, 1]]);
+                _ => return None,
+            }
+        }
+        if columns[0] == columns[1] || columns[0] == columns[2] || columns[1] == columns[2] {
+            return None;
+        }
+        Some(columns[0] | columns[1] << 2 | signs[0] << 4 | signs[1] << 5 | signs[2] << 6)
+    }
+}
 
-        Self {
-            t, r
+/// The ways in which a scene graph can be malformed
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ScanError {
+    /// A node refers to a child node which doesn't exist.
+    MissingNode(u32),
+    /// A node can't be the child of its parent (e.g. a Transform node directly beneath another
+    /// Transform node).
+    InvalidChild {
+        /// The ID of the parent node.
+        parent: u32,
+        /// The ID of the child node.
+        child: u32,
+    },
+    /// The graph has no root node, as every Transform and Group node is the child of another
+    /// node.
+    BadRoot,
+    /// A Shape node isn't the child of any node, so has no transform and isn't part of the scene.
+    DetachedShape(u32),
+    /// A node is one of its own ancestors, so the graph contains a cycle.
+    Cycle(u32),
+    /// A node is nested more than `MAX_SCENE_DEPTH` levels deep.
+    TooDeep(u32),
+    /// Collapsing the graph would visit more than `MAX_SCENE_VISITS` nodes, as subtrees are
+    /// shared between so many parents. Nothing after the limit is visited.
+    TooManyVisits,
+    /// A frame's `_r` attribute isn't a valid rotation.
+    InvalidRotation(String),
+    /// A frame's `_t` attribute isn't a valid translation.
+    InvalidTranslation(String),
+}
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScanError::MissingNode(id) =>
+                write!(f, "Scene graph contains an id for a node which doesn't exist (id: {})", id),
+            ScanError::InvalidChild { parent, child } =>
+                write!(f, "Unknown scene graph format: node {} can't be a child of node {}", child, parent),
+            ScanError::BadRoot =>
+                write!(f, "Unknown scene graph format: every node is the child of another node"),
+            ScanError::DetachedShape(id) =>
+                write!(f, "Scene graph contains a shape node without a parent (id: {})", id),
+            ScanError::Cycle(id) =>
+                write!(f, "Scene graph contains a cycle (node id: {})", id),
+            ScanError::TooDeep(id) =>
+                write!(f, "Scene graph is nested too deeply (node id: {})", id),
+            ScanError::TooManyVisits =>
+                write!(f, "Scene graph shares its nodes too many times to be collapsed"),
+            ScanError::InvalidRotation(r) => write!(f, "Unknown rotation format: {}", r),
+            ScanError::InvalidTranslation(t) => write!(f, "Unknown translation format: {}", t),
         }
     }
 }
+impl Error for ScanError {}
+
+/// The maximum depth of nodes which will be visited when collapsing a scene graph.
+///
+/// MagicaVoxel itself only nests a handful of levels deep, so this is only reached by malformed
+/// or malicious files.
+pub const MAX_SCENE_DEPTH: usize = 1024;
+
+/// The maximum number of nodes which will be visited (and errors which will be reported) when
+/// collapsing a scene graph.
+///
+/// A node shared between several parents is visited once for each of them, so a malformed or
+/// malicious graph which shares subtrees at every level would otherwise take exponential time.
+pub const MAX_SCENE_VISITS: usize = 1 << 20;
 
-pub struct SceneGraph(HashMap<u32, NodeKind>);
+/// The scene graph of a .vox file, holding every node by its ID
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SceneGraph(Map<u32, Node>);
 impl SceneGraph {
+    /// Creates an empty scene graph.
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self(Map::new())
     }
+    /// Adds a node to the graph, replacing any existing node with the same ID.
     pub fn add_node(&mut self, node: Node) {
-        self.0.insert(node.id, node.kind);
-    }
-    pub fn collapse_to_vec(self) -> Vec<(Transform, usize)> {
-        // Assume that we have no cycles
-        // Assume root node id is 0 and it is a Transform node
-        if let Some(NodeKind::Transform{ child_id, transform }) = self.0.get(&0) {
-            self.collapse_transform(*child_id, vec![*transform])
-                .iter()
-                .map(|(transforms, id)| (
-                    transforms
-                        .iter()
-                        .fold(
-                            Transform::default(),
-                            |transform, next| transform.apply(*next),
-                        ),
-                    *id,
-                )).collect::<Vec<_>>()
-        } else {
-            debug!("Unknown scene graph format: node 0 is not a Transform node");
-            vec![]
+        self.0.insert(node.id, node);
+    }
+    /// Looks up a node by its ID.
+    pub fn get(&self, id: u32) -> Option<&Node> {
+        self.0.get(&id)
+    }
+    /// An iterator over all nodes in the graph, in no particular order.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.0.values()
+    }
+    /// An iterator over mutable references to all nodes in the graph, in no particular order.
+    ///
+    /// Nodes keep the IDs they were added with, so changing `Node::id` leaves them findable
+    /// only by their old ID.
+    pub fn nodes_mut(&mut self) -> impl Iterator<Item = &mut Node> {
+        self.0.values_mut()
+    }
+    /// The number of nodes in the graph.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    /// Whether the graph contains no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Collapses the graph into a list of world transformations paired with model indices.
+    ///
+    /// Malformed parts of the graph are skipped over (logging why at debug level); use
+    /// `try_collapse_to_vec` to have them reported instead.
+    pub fn collapse_to_vec(&self) -> Vec<(Transform, usize)> {
+        self.iter_shapes()
+            .map(|shape| (shape.transform, shape.model_id))
+            .collect()
+    }
+    /// Collapses the graph like `collapse_to_vec`, additionally pairing each model with the name
+    /// of its nearest named ancestor Transform node.
+    pub fn collapse_to_named_vec(&self) -> Vec<(Transform, usize, Option<String>)> {
+        self.iter_shapes()
+            .map(|shape| (shape.transform, shape.model_id, shape.name.map(str::to_owned)))
+            .collect()
+    }
+    /// Collapses the graph like `collapse_to_vec`, but fails on the first malformed part of the
+    /// graph rather than skipping over it.
+    pub fn try_collapse_to_vec(&self) -> Result<Vec<(Transform, usize)>, ScanError> {
+        self.try_iter_shapes()
+            .map(|shape| shape.map(|shape| (shape.transform, shape.model_id)))
+            .collect()
+    }
+    /// Collapses the graph like `collapse_to_named_vec`, but fails on the first malformed part of
+    /// the graph rather than skipping over it.
+    pub fn try_collapse_to_named_vec(&self) -> Result<Vec<(Transform, usize, Option<String>)>, ScanError> {
+        self.try_iter_shapes()
+            .map(|shape| shape.map(|shape| (shape.transform, shape.model_id, shape.name.map(str::to_owned))))
+            .collect()
+    }
+    /// An iterator over every Shape node reachable from the roots of the graph, depth first and
+    /// in the order children are listed by their parents.
+    ///
+    /// Malformed parts of the graph are skipped over, logging why at debug level.
+    pub fn iter_shapes(&self) -> impl Iterator<Item = ShapeInstance<'_>> {
+        ShapeIter::new(self, false).filter_map(|shape| shape
+            .map_err(|error| debug!("{}", error))
+            .ok())
+    }
+    /// An iterator over every Shape node like `iter_shapes`, which yields an error for each
+    /// malformed part of the graph rather than skipping over it.
+    pub fn try_iter_shapes(&self) -> ShapeIter<'_> {
+        ShapeIter::new(self, true)
+    }
+    /// The IDs of all Transform and Group nodes which aren't the child of any other node, in
+    /// ascending order.
+    ///
+    /// A well formed graph has a single root, usually a Transform node with ID 0. Shape nodes
+    /// are never roots, as a Shape without a parent has no transform to place it in the world.
+    pub fn roots(&self) -> Vec<u32> {
+        self.parentless(false)
+    }
+    /// The IDs of the nodes which aren't the child of any other node, in ascending order: either
+    /// the Shape nodes, or every other node.
+    fn parentless(&self, shapes: bool) -> Vec<u32> {
+        let children = self.0.values()
+            .flat_map(|node| match &node.kind {
+                NodeKind::Transform { child_id, .. } => vec![*child_id],
+                NodeKind::Group { children_ids } => children_ids.clone(),
+                NodeKind::Shape { .. } => vec![],
+            })
+            .collect::<Set<_>>();
+        let mut roots = self.0.iter()
+            .filter(|(id, node)| !children.contains(id) && matches!(node.kind, NodeKind::Shape { .. }) == shapes)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        roots.sort();
+        roots
+    }
+}
+
+
+/// A Shape node reached while traversing a scene graph, along with everything accumulated on
+/// the way to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeInstance<'a> {
+    /// The index of the displayed model within `DotVoxData::models`.
+    pub model_id: usize,
+    /// The world transformation of the model, composed from all of its ancestor Transform nodes.
+    pub transform: Transform,
+    /// The IDs of the nodes leading from the root of the graph to the Shape node, inclusive.
+    pub path: Vec<u32>,
+    /// The name of the nearest named ancestor Transform node.
+    pub name: Option<&'a str>,
+    /// The layer of the nearest ancestor Transform node which belongs to a layer.
+    pub layer_id: Option<u32>,
+    /// Whether any node on `path` has been hidden in MagicaVoxel, hiding the model. Models can
+    /// also be hidden by hiding their layer; see `DotVoxData::visible_shapes`.
+    pub hidden: bool,
+}
+
+/// An iterator over the shapes of a scene graph, created by `SceneGraph::try_iter_shapes`
+pub struct ShapeIter<'a> {
+    graph: &'a SceneGraph,
+    strict: bool,
+    stack: Vec<PendingNode<'a>>,
+    visits: usize,
+    path: Vec<u32>,
+    errors: VecDeque<ScanError>,
+}
+
+struct PendingNode<'a> {
+    id: u32,
+    depth: usize,
+    transform: Transform,
+    name: Option<&'a str>,
+    layer_id: Option<u32>,
+    hidden: bool,
+}
+
+impl<'a> ShapeIter<'a> {
+    fn new(graph: &'a SceneGraph, strict: bool) -> Self {
+        let roots = graph.roots();
+        let bad_root = roots.is_empty() && !graph.is_empty();
+        let stack = roots
+            .into_iter()
+            .rev()
+            .map(|id| PendingNode {
+                id,
+                depth: 0,
+                transform: Transform::default(),
+                name: None,
+                layer_id: None,
+                hidden: false,
+            })
+            .collect();
+        let mut iter = ShapeIter { graph, strict, stack, visits: 0, path: Vec::new(), errors: VecDeque::new() };
+        if bad_root {
+            iter.report(ScanError::BadRoot);
+        }
+        for id in graph.parentless(true) {
+            iter.report(ScanError::DetachedShape(id));
+        }
+        iter
+    }
+
+    /// Counts a visit against `MAX_SCENE_VISITS`, returning `false` (and stopping the walk) once
+    /// the limit has been passed.
+    fn count_visit(&mut self) -> bool {
+        self.visits += 1;
+        if self.visits == MAX_SCENE_VISITS + 1 {
+            self.errors.push_back(ScanError::TooManyVisits);
+            self.stack.clear();
+        }
+        self.visits <= MAX_SCENE_VISITS
+    }
+
+    /// Queues an error to be yielded, which counts as a visit so that malformed graphs can't
+    /// build up an unbounded queue of errors.
+    fn report(&mut self, error: ScanError) {
+        if self.count_visit() {
+            self.errors.push_back(error);
         }
     }
-    fn collapse_transform(&self, child: u32, transforms: Vec<Transform>) -> Vec<(Vec<Transform>, usize)> {
-        let mut collapsed = Vec::new();
-
-        if let Some(node) = self.0.get(&child) {
-            match node {
-                NodeKind::Group{ children_ids } => {
-                    for id in children_ids {
-                        match self.0.get(id) {
-                            Some(NodeKind::Transform{ child_id, transform }) => {
-                                let mut new_transforms = vec![*transform];
-                                new_transforms.extend_from_slice(&transforms);
-                                collapsed.append(&mut self.collapse_transform(*child_id, new_transforms));
-                            }
-                            Some(_) => {
-                                debug!("Unknown scene graph format: non-Transform node found as Group node child");
-                            }
-                            None => {
-                                debug!("Scene graph contains an id for a node which doesn't exist (id: {})", id);
-                            }
+
+    fn visit(&mut self, pending: PendingNode<'a>) -> Option<ShapeInstance<'a>> {
+        // Walk the graph with an explicit stack, so that deep graphs can't overflow the call
+        // stack. Nodes shared between several parents are visited once for each, but a node
+        // which is already on the path to itself is a cycle and isn't followed.
+        if !self.count_visit() {
+            return None;
+        }
+        if pending.depth > MAX_SCENE_DEPTH {
+            self.report(ScanError::TooDeep(pending.id));
+            return None;
+        }
+        // Everything still on the path above this node's depth is one of its ancestors
+        self.path.truncate(pending.depth);
+        if self.path.contains(&pending.id) {
+            self.report(ScanError::Cycle(pending.id));
+            return None;
+        }
+        let node = self.graph.0.get(&pending.id)?;
+        self.path.push(pending.id);
+        let hidden = pending.hidden || node.hidden();
+
+        match &node.kind {
+            NodeKind::Transform { child_id, layer_id, frames } => {
+                match self.graph.0.get(child_id) {
+                    Some(Node { kind: NodeKind::Transform { .. }, .. }) => {
+                        self.report(ScanError::InvalidChild { parent: pending.id, child: *child_id });
+                    }
+                    Some(_) => {
+                        let local = match frames.first() {
+                            Some(frame) if self.strict => match Transform::try_from_dict(&frame.attributes) {
+                                Ok(transform) => transform,
+                                Err(error) => {
+                                    self.report(error);
+                                    return None;
+                                }
+                            },
+                            Some(frame) => frame.transform(),
+                            None => Transform::default(),
+                        };
+                        self.stack.push(PendingNode {
+                            id: *child_id,
+                            depth: pending.depth + 1,
+                            transform: local.apply(pending.transform),
+                            name: node.name().or(pending.name),
+                            layer_id: if *layer_id == u32::MAX { pending.layer_id } else { Some(*layer_id) },
+                            hidden,
+                        });
+                    }
+                    None => self.report(ScanError::MissingNode(*child_id)),
+                }
+                None
+            }
+            NodeKind::Group { children_ids } => {
+                let mut children = Vec::with_capacity(children_ids.len());
+                // Errors are only logged when not strict, so one for each group is plenty
+                let mut reported = false;
+                for child_id in children_ids {
+                    let error = match self.graph.0.get(child_id) {
+                        Some(Node { kind: NodeKind::Transform { .. }, .. }) => {
+                            children.push(PendingNode { id: *child_id, depth: pending.depth + 1, hidden, ..pending });
+                            continue;
                         }
+                        Some(_) => ScanError::InvalidChild { parent: pending.id, child: *child_id },
+                        None => ScanError::MissingNode(*child_id),
+                    };
+                    if self.strict || !reported {
+                        self.report(error);
+                        reported = true;
                     }
                 }
-                NodeKind::Shape { model_id } => collapsed.push((transforms, *model_id as usize)),
-                NodeKind::Transform { .. } =>  debug!("Unknown scene graph format: Transform node found as Transform node child"),
+                if self.visits <= MAX_SCENE_VISITS {
+                    self.stack.extend(children.into_iter().rev());
+                }
+                None
             }
-        } else {
-            debug!("Scene graph contains an id for a node which doesn't exist (id: {})", child);
+            NodeKind::Shape { model_id } => Some(ShapeInstance {
+                model_id: *model_id as usize,
+                transform: pending.transform,
+                path: self.path.clone(),
+                name: pending.name,
+                layer_id: pending.layer_id,
+                hidden,
+            }),
         }
-
-        collapsed
     }
 }
 
+impl<'a> Iterator for ShapeIter<'a> {
+    type Item = Result<ShapeInstance<'a>, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(error) = self.errors.pop_front() {
+                return Some(Err(error));
+            }
+            let pending = self.stack.pop()?;
+            if let Some(shape) = self.visit(pending) {
+                return Some(Ok(shape));
+            }
+        }
+    }
+}
 
 named!(pub parse_group_node <CompleteByteSlice, Node>, do_parse!(
     id: le_u32 >>
-    _attributes: parse_dict >>
-    num_children: le_u32 >>
-    children_ids: many_m_n!(num_children as usize, num_children as usize, le_u32) >>
-    (Node { id, kind: NodeKind::Group { children_ids } })
+    attributes: parse_dict >>
+    num_children: call!(le_count, 4) >>
+    children_ids: many_m_n!(num_children, num_children, le_u32) >>
+    (Node { id, attributes, kind: NodeKind::Group { children_ids } })
 ));
 
 named!(pub parse_transform_node <CompleteByteSlice, Node>, do_parse!(
     id: le_u32 >>
-    _attributes: parse_dict >>
+    attributes: parse_dict >>
     child_id: le_u32 >>
     _reserved_id: le_u32 >> // must be -1
-    _layer_id: le_u32 >>
-    _num_frames: le_u32 >> // must be 1
-    transform_dict: parse_dict >>
-    (Node { id, kind: NodeKind::Transform { child_id, transform: Transform::from_dict(transform_dict) } })
+    layer_id: le_u32 >>
+    num_frames: call!(le_count, 4) >>
+    frames: many_m_n!(num_frames, num_frames, parse_frame) >>
+    (Node { id, attributes, kind: NodeKind::Transform { child_id, layer_id, frames } })
+));
+
+named!(parse_frame <CompleteByteSlice, Frame>, do_parse!(
+    attributes: parse_dict >>
+    (Frame { attributes })
 ));
 
 named!(pub parse_shape_node <CompleteByteSlice, Node>, do_parse!(
     id: le_u32 >>
-    _attributes: parse_dict >>
+    attributes: parse_dict >>
     _num_models: le_u32 >> // must be 1
     model_id: parse_model_entry >>
-    (Node { id, kind: NodeKind::Shape { model_id } })
+    (Node { id, attributes, kind: NodeKind::Shape { model_id } })
 ));
 
 named!(parse_model_entry <CompleteByteSlice, u32>, do_parse!(
     id: le_u32 >>
     _attributes: parse_dict >>
     (id)
-));
\ No newline at end of file
+));
+
+named!(pub parse_layer_node <CompleteByteSlice, Layer>, do_parse!(
+    id: le_u32 >>
+    attributes: parse_dict >>
+    _reserved_id: le_u32 >> // must be -1
+    (Layer { id, attributes })
+));
+
+/// Builders for the nodes of scene graphs made up by tests
+#[cfg(test)]
+pub mod test_nodes {
+    use super::*;
+
+    pub fn transform_node(id: u32, attributes: Dict, child_id: u32, t: [i32; 3]) -> Node {
+        let mut frame = Dict::new();
+        frame.insert("_t".to_owned(), format!("{} {} {}", t[0], t[1], t[2]));
+        Node {
+            id,
+            attributes,
+            kind: NodeKind::Transform {
+                child_id,
+                layer_id: 0,
+                frames: vec![Frame { attributes: frame }],
+            },
+        }
+    }
+
+    pub fn group_node(id: u32, children_ids: Vec<u32>) -> Node {
+        Node { id, attributes: Dict::new(), kind: NodeKind::Group { children_ids } }
+    }
+
+    pub fn shape_node(id: u32, model_id: u32) -> Node {
+        Node { id, attributes: Dict::new(), kind: NodeKind::Shape { model_id } }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_nodes::*;
+
+    fn named(name: &str) -> Dict {
+        let mut attributes = Dict::new();
+        attributes.insert("_name".to_owned(), name.to_owned());
+        attributes
+    }
+
+    #[test]
+    fn collapsed_models_carry_the_nearest_ancestor_name() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(transform_node(0, named("root"), 1, [0, 0, 0]));
+        graph.add_node(group_node(1, vec![2, 4, 6]));
+        graph.add_node(transform_node(2, named("turret base"), 3, [1, 0, 0]));
+        graph.add_node(shape_node(3, 0));
+        graph.add_node(transform_node(4, Dict::new(), 5, [0, 2, 0]));
+        graph.add_node(shape_node(5, 1));
+        graph.add_node(transform_node(6, named("ä"), 7, [0, 0, 3]));
+        graph.add_node(group_node(7, vec![8]));
+        graph.add_node(transform_node(8, Dict::new(), 9, [0, 0, 1]));
+        graph.add_node(shape_node(9, 2));
+
+        let names = graph.collapse_to_named_vec()
+            .into_iter()
+            .map(|(transform, model, name)| (transform.t, model, name))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![
+            ([1, 0, 0], 0, Some("turret base".to_owned())),
+            ([0, 2, 0], 1, Some("root".to_owned())),
+            ([0, 0, 4], 2, Some("ä".to_owned())),
+        ]);
+    }
+
+    fn signed_permutation_matrices() -> Vec<[[i8; 3]; 3]> {
+        let permutations = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+        let mut matrices = Vec::new();
+        for permutation in &permutations {
+            for signs in 0..8 {
+                let mut r = [[0; 3]; 3];
+                for row in 0..3 {
+                    r[row][permutation[row]] = if signs >> row & 1 == 0 { 1 } else { -1 };
+                }
+                matrices.push(r);
+            }
+        }
+        matrices
+    }
+
+    #[test]
+    fn every_valid_rotation_round_trips_through_a_byte() {
+        let matrices = signed_permutation_matrices();
+        assert_eq!(matrices.len(), 48);
+        for r in matrices {
+            let byte = Transform::rotation_to_byte(r).expect("valid rotation");
+            assert_eq!(Transform::rotation_from_byte(byte), Some(r));
+        }
+    }
+
+    #[test]
+    fn every_valid_byte_round_trips_through_a_rotation() {
+        let valid = (0..=255u8)
+            .filter_map(|n| Transform::rotation_from_byte(n).map(|r| (n, r)))
+            .collect::<Vec<_>>();
+        assert_eq!(valid.len(), 48);
+        for (n, r) in valid {
+            assert_eq!(Transform::rotation_to_byte(r), Some(n));
+        }
+    }
+
+    #[test]
+    fn invalid_rotations_cannot_be_encoded() {
+        // Two non-zero entries in a row
+        assert_eq!(Transform::rotation_to_byte([[1, 1, 0], [0, 1, 0], [0, 0, 1]]), None);
+        // Two rows sharing a column
+        assert_eq!(Transform::rotation_to_byte([[1, 0, 0], [1, 0, 0], [0, 0, 1]]), None);
+        // An empty row
+        assert_eq!(Transform::rotation_to_byte([[1, 0, 0], [0, 0, 0], [0, 0, 1]]), None);
+        // A scaling entry
+        assert_eq!(Transform::rotation_to_byte([[2, 0, 0], [0, 1, 0], [0, 0, 1]]), None);
+        let transform = Transform { t: [0; 3], r: [[0; 3]; 3] };
+        assert_eq!(transform.to_dict(), None);
+    }
+
+    #[test]
+    fn transforms_round_trip_through_a_dict() {
+        for r in signed_permutation_matrices() {
+            let transform = Transform { t: [-3, 0, 17], r };
+            let dict = transform.to_dict().unwrap();
+            assert_eq!(dict.get("_t"), Some(&"-3 0 17".to_owned()));
+            assert_eq!(Transform::from_dict(&dict), transform);
+        }
+    }
+
+    #[test]
+    fn extreme_translations_wrap_around() {
+        let far = Transform { t: [i32::MAX, i32::MIN, 0], r: [[-1, 0, 0], [0, 0, 1], [0, -1, 0]] };
+        assert_eq!(far.transform_point([1, 1, i32::MIN]), [i32::MAX - 1, 0, -1]);
+        assert_eq!(far.apply(far).t, [0, i32::MIN, i32::MIN]);
+    }
+
+    #[test]
+    fn inverses_undo_every_rotation() {
+        let p = [4, -5, 6];
+        for r in signed_permutation_matrices() {
+            let transform = Transform { t: [-3, 8, i32::MIN], r };
+            let inverse = transform.inverse();
+            assert_eq!(inverse.transform_point(transform.transform_point(p)), p);
+            assert_eq!(transform.apply(inverse), Transform::default());
+            assert_eq!(inverse.apply(transform), Transform::default());
+            assert_eq!(inverse.inverse(), transform);
+        }
+    }
+
+    #[test]
+    fn applying_a_transform_composes_first_then_second() {
+        let rotate_z = Transform { t: [1, 2, 3], r: [[0, -1, 0], [1, 0, 0], [0, 0, 1]] };
+        let flip_x = Transform { t: [0, 0, 10], r: [[-1, 0, 0], [0, 1, 0], [0, 0, 1]] };
+        let p = [4, 5, 6];
+
+        assert_eq!(rotate_z.transform_point(p), [-4, 6, 9]);
+        assert_eq!(flip_x.transform_point([-4, 6, 9]), [4, 6, 19]);
+        assert_eq!(rotate_z.apply(flip_x).transform_point(p), [4, 6, 19]);
+        assert_eq!(flip_x.apply(rotate_z).transform_point(p), rotate_z.transform_point(flip_x.transform_point(p)));
+        assert_eq!(Transform::default().apply(rotate_z), rotate_z);
+        assert_eq!(rotate_z.apply(Transform::default()), rotate_z);
+    }
+
+    #[test]
+    fn root_is_found_when_it_is_not_node_zero() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(transform_node(7, Dict::new(), 3, [0, 0, 2]));
+        graph.add_node(group_node(3, vec![5]));
+        graph.add_node(transform_node(5, Dict::new(), 0, [1, 0, 0]));
+        graph.add_node(shape_node(0, 4));
+
+        assert_eq!(graph.roots(), vec![7]);
+        let scene = graph.collapse_to_vec();
+        assert_eq!(scene.len(), 1);
+        assert_eq!(scene[0].0.t, [1, 0, 2]);
+        assert_eq!(scene[0].1, 4);
+    }
+
+    #[test]
+    fn group_roots_have_an_identity_transform() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(group_node(1, vec![2, 4]));
+        graph.add_node(transform_node(2, Dict::new(), 3, [1, 2, 3]));
+        graph.add_node(shape_node(3, 0));
+        graph.add_node(transform_node(4, Dict::new(), 5, [-1, 0, 0]));
+        graph.add_node(shape_node(5, 1));
+
+        let scene = graph.collapse_to_vec()
+            .into_iter()
+            .map(|(transform, model)| (transform.t, model))
+            .collect::<Vec<_>>();
+        assert_eq!(scene, vec![([1, 2, 3], 0), ([-1, 0, 0], 1)]);
+    }
+
+    #[test]
+    fn detached_shapes_are_not_roots() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(group_node(1, vec![2]));
+        graph.add_node(transform_node(2, Dict::new(), 3, [1, 2, 3]));
+        graph.add_node(shape_node(3, 0));
+        graph.add_node(shape_node(8, 1));
+
+        assert_eq!(graph.roots(), vec![1]);
+        assert_eq!(graph.try_collapse_to_vec(), Err(ScanError::DetachedShape(8)));
+        let scene = graph.collapse_to_vec()
+            .into_iter()
+            .map(|(transform, model)| (transform.t, model))
+            .collect::<Vec<_>>();
+        assert_eq!(scene, vec![([1, 2, 3], 0)]);
+    }
+
+    #[test]
+    fn multiple_roots_are_concatenated() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(transform_node(10, Dict::new(), 11, [0, 0, 5]));
+        graph.add_node(shape_node(11, 1));
+        graph.add_node(transform_node(2, Dict::new(), 3, [0, 6, 0]));
+        graph.add_node(shape_node(3, 0));
+
+        assert_eq!(graph.roots(), vec![2, 10]);
+        let scene = graph.collapse_to_vec()
+            .into_iter()
+            .map(|(transform, model)| (transform.t, model))
+            .collect::<Vec<_>>();
+        assert_eq!(scene, vec![([0, 6, 0], 0), ([0, 0, 5], 1)]);
+    }
+
+    #[test]
+    fn cycles_are_not_followed() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(transform_node(0, Dict::new(), 1, [0, 0, 0]));
+        graph.add_node(group_node(1, vec![2, 4]));
+        graph.add_node(transform_node(2, Dict::new(), 3, [1, 0, 0]));
+        graph.add_node(shape_node(3, 0));
+        // Node 4 points back up at the group containing it
+        graph.add_node(transform_node(4, Dict::new(), 1, [0, 1, 0]));
+
+        let scene = graph.collapse_to_vec();
+        assert_eq!(scene.len(), 1);
+        assert_eq!(scene[0].0.t, [1, 0, 0]);
+    }
+
+    #[test]
+    fn shared_subtrees_are_collapsed_for_each_parent() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(group_node(0, vec![1, 2]));
+        graph.add_node(transform_node(1, Dict::new(), 3, [10, 0, 0]));
+        graph.add_node(transform_node(2, Dict::new(), 3, [20, 0, 0]));
+        graph.add_node(group_node(3, vec![4]));
+        graph.add_node(transform_node(4, Dict::new(), 5, [0, 1, 0]));
+        graph.add_node(shape_node(5, 0));
+
+        let shapes = graph.try_iter_shapes()
+            .map(|shape| shape.map(|shape| (shape.transform.t, shape.path)))
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(shapes, Ok(vec![([10, 1, 0], vec![0, 1, 3, 4, 5]), ([20, 1, 0], vec![0, 2, 3, 4, 5])]));
+    }
+
+    #[test]
+    fn exponentially_shared_graphs_are_cut_short() {
+        // Each level shares the next between two Transform nodes, doubling the paths to the shape
+        let mut graph = SceneGraph::new();
+        let levels = 40;
+        for level in 0..levels {
+            let id = level * 3;
+            graph.add_node(group_node(id, vec![id + 1, id + 2]));
+            graph.add_node(transform_node(id + 1, Dict::new(), id + 3, [0, 0, 0]));
+            graph.add_node(transform_node(id + 2, Dict::new(), id + 3, [0, 0, 1]));
+        }
+        graph.add_node(group_node(levels * 3, vec![levels * 3 + 1]));
+        graph.add_node(transform_node(levels * 3 + 1, Dict::new(), levels * 3 + 2, [0, 0, 0]));
+        graph.add_node(shape_node(levels * 3 + 2, 0));
+
+        assert_eq!(graph.try_collapse_to_vec(), Err(ScanError::TooManyVisits));
+    }
+
+    #[test]
+    fn groups_of_missing_children_are_reported_in_linear_time() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(transform_node(200_001, Dict::new(), 200_002, [0, 0, 0]));
+        graph.add_node(shape_node(200_002, 0));
+        let mut children = (1..=200_000).collect::<Vec<_>>();
+        children.push(200_001);
+        graph.add_node(group_node(0, children));
+
+        // Only the first missing child of each group is logged when collapsing leniently
+        assert_eq!(graph.collapse_to_vec().len(), 1);
+        let errors = graph.try_iter_shapes().filter_map(Result::err).collect::<Vec<_>>();
+        assert_eq!(errors.len(), 200_000);
+        assert_eq!(errors[0], ScanError::MissingNode(1));
+        assert_eq!(errors[199_999], ScanError::MissingNode(200_000));
+
+        // Errors count against the limit on visits, so can't pile up without bound
+        graph.add_node(group_node(0, (1..=MAX_SCENE_VISITS as u32 + 10).collect()));
+        let errors = graph.try_iter_shapes().filter_map(Result::err).collect::<Vec<_>>();
+        assert_eq!(errors.len(), MAX_SCENE_VISITS);
+        assert_eq!(errors.last(), Some(&ScanError::TooManyVisits));
+    }
+
+    #[test]
+    fn graphs_without_roots_collapse_to_nothing() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(transform_node(0, Dict::new(), 1, [0, 0, 0]));
+        graph.add_node(group_node(1, vec![0]));
+
+        assert!(graph.roots().is_empty());
+        assert!(graph.collapse_to_vec().is_empty());
+    }
+
+    fn nested_graph(levels: u32) -> SceneGraph {
+        let mut graph = SceneGraph::new();
+        for level in 0..levels {
+            graph.add_node(transform_node(level * 2, Dict::new(), level * 2 + 1, [0, 0, 1]));
+            graph.add_node(group_node(level * 2 + 1, vec![level * 2 + 2]));
+        }
+        graph.add_node(transform_node(levels * 2, Dict::new(), levels * 2 + 1, [0, 0, 1]));
+        graph.add_node(shape_node(levels * 2 + 1, 0));
+        graph
+    }
+
+    #[test]
+    fn deeply_nested_graphs_are_collapsed() {
+        let scene = nested_graph(100).collapse_to_vec();
+        assert_eq!(scene.len(), 1);
+        assert_eq!(scene[0].0.t, [0, 0, 101]);
+    }
+
+    #[test]
+    fn pathologically_nested_graphs_do_not_overflow_the_stack() {
+        assert!(nested_graph(100_000).collapse_to_vec().is_empty());
+    }
+
+    #[test]
+    fn strict_collapse_reports_malformed_graphs() {
+        let mut graph = nested_graph(1);
+        assert_eq!(graph.try_collapse_to_vec().map(|scene| scene.len()), Ok(1));
+
+        graph.add_node(group_node(1, vec![2, 9]));
+        assert_eq!(graph.try_collapse_to_vec(), Err(ScanError::MissingNode(9)));
+        assert_eq!(graph.collapse_to_vec().len(), 1);
+
+        graph.add_node(group_node(1, vec![2, 3]));
+        assert_eq!(graph.try_collapse_to_vec(), Err(ScanError::InvalidChild { parent: 1, child: 3 }));
+
+        graph.add_node(group_node(1, vec![2, 0]));
+        assert_eq!(graph.try_collapse_to_vec(), Err(ScanError::BadRoot));
+
+        let mut graph = nested_graph(1);
+        graph.add_node(transform_node(4, Dict::new(), 1, [0, 0, 0]));
+        graph.add_node(group_node(1, vec![2, 4]));
+        assert_eq!(graph.try_collapse_to_vec(), Err(ScanError::Cycle(1)));
+
+        assert_eq!(
+            nested_graph(MAX_SCENE_DEPTH as u32).try_collapse_to_vec().map(|scene| scene.len()),
+            Err(ScanError::TooDeep(MAX_SCENE_DEPTH as u32 + 1))
+        );
+    }
+
+    #[test]
+    fn strict_collapse_reports_malformed_frames() {
+        let mut graph = nested_graph(1);
+        let mut frame = Dict::new();
+        frame.insert("_r".to_owned(), "255".to_owned());
+        graph.add_node(Node {
+            id: 2,
+            attributes: Dict::new(),
+            kind: NodeKind::Transform { child_id: 3, layer_id: 0, frames: vec![Frame { attributes: frame }] },
+        });
+        assert_eq!(graph.try_collapse_to_vec(), Err(ScanError::InvalidRotation("255".to_owned())));
+        assert_eq!(graph.collapse_to_vec()[0].0, Transform { t: [0, 0, 1], ..Transform::default() });
+
+        let mut frame = Dict::new();
+        frame.insert("_t".to_owned(), "1 x 2 3".to_owned());
+        assert_eq!(Transform::try_from_dict(&frame), Err(ScanError::InvalidTranslation("1 x 2 3".to_owned())));
+        assert_eq!(Transform::from_dict(&frame), Transform::default());
+    }
+
+    #[test]
+    fn shape_instances_carry_their_path_and_nearest_attributes() {
+        let mut graph = SceneGraph::new();
+        graph.add_node(transform_node(0, named("root"), 1, [0, 0, 0]));
+        graph.add_node(group_node(1, vec![2, 6]));
+        graph.add_node(Node {
+            id: 2,
+            attributes: named("arms"),
+            kind: NodeKind::Transform { child_id: 3, layer_id: 4, frames: vec![] },
+        });
+        graph.add_node(group_node(3, vec![4]));
+        graph.add_node(transform_node(4, Dict::new(), 5, [0, 0, 3]));
+        graph.add_node(shape_node(5, 1));
+        graph.add_node(transform_node(6, Dict::new(), 7, [2, 0, 0]));
+        graph.add_node(shape_node(7, 0));
+
+        let shapes = graph.iter_shapes().collect::<Vec<_>>();
+        assert_eq!(shapes, vec![
+            ShapeInstance {
+                model_id: 1,
+                transform: Transform { t: [0, 0, 3], ..Transform::default() },
+                path: vec![0, 1, 2, 3, 4, 5],
+                name: Some("arms"),
+                // transform_node puts nodes on layer 0, and node 4 is nearer than node 2
+                layer_id: Some(0),
+                hidden: false,
+            },
+            ShapeInstance {
+                model_id: 0,
+                transform: Transform { t: [2, 0, 0], ..Transform::default() },
+                path: vec![0, 1, 6, 7],
+                name: Some("root"),
+                layer_id: Some(0),
+                hidden: false,
+            },
+        ]);
+    }
+
+    #[test]
+    fn hiding_a_node_hides_every_shape_below_it() {
+        let mut hidden = named("left_arm");
+        hidden.insert("_hidden".to_owned(), "1".to_owned());
+        let mut graph = SceneGraph::new();
+        graph.add_node(transform_node(0, Dict::new(), 1, [0, 0, 0]));
+        graph.add_node(group_node(1, vec![2, 5, 7]));
+        graph.add_node(transform_node(2, hidden.clone(), 3, [0, 0, 0]));
+        graph.add_node(group_node(3, vec![4]));
+        graph.add_node(transform_node(4, Dict::new(), 9, [0, 0, 0]));
+        graph.add_node(shape_node(9, 0));
+        graph.add_node(transform_node(5, named("right_arm"), 6, [0, 0, 0]));
+        graph.add_node(shape_node(6, 1));
+        graph.add_node(transform_node(7, Dict::new(), 8, [0, 0, 0]));
+        graph.add_node(Node { id: 8, attributes: hidden, kind: NodeKind::Shape { model_id: 2 } });
+
+        let shapes = graph.iter_shapes()
+            .map(|shape| (shape.model_id, shape.hidden))
+            .collect::<Vec<_>>();
+        assert_eq!(shapes, vec![(0, true), (1, false), (2, true)]);
+    }
+
+    #[test]
+    fn strict_iteration_yields_every_error() {
+        let mut graph = nested_graph(1);
+        graph.add_node(group_node(1, vec![8, 2, 9]));
+        let shapes = graph.try_iter_shapes()
+            .map(|shape| shape.map(|shape| shape.model_id))
+            .collect::<Vec<_>>();
+        assert_eq!(shapes, vec![Err(ScanError::MissingNode(8)), Err(ScanError::MissingNode(9)), Ok(0)]);
+    }
+
+    #[test]
+    fn can_parse_a_transform_node_with_multiple_frames() {
+        let bytes = include_bytes!("resources/valid_animated_transform.bytes").to_vec();
+        let result = parse_transform_node(CompleteByteSlice(&bytes));
+        match result {
+            Ok((rest, node)) => {
+                assert!(rest.is_empty());
+                match node.kind {
+                    NodeKind::Transform { child_id: 3, layer_id: 0, ref frames } => {
+                        assert_eq!(frames.iter().map(Frame::index).collect::<Vec<_>>(), vec![0, 4, 9]);
+                        assert_eq!(
+                            frames.iter().map(|frame| frame.transform().t).collect::<Vec<_>>(),
+                            vec![[0, 0, 0], [0, 0, 5], [1, -2, 10]]
+                        );
+                    }
+                    ref kind => panic!("Expected Transform node, got {:?}", kind),
+                }
+                assert_eq!(node.kind.transform(), Some(Transform::default()));
+            }
+            _ => panic!("Expected Done, got {:?}", result)
+        }
+    }
+}
@@ -0,0 +1,287 @@
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use std::error::Error;
+use std::fmt;
+use {DotVoxData, NodeKind, ScanError, Size};
+
+/// A problem found in a `DotVoxData` by `DotVoxData::validate`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ValidationIssue {
+    /// A voxel lies outside the bounds given by its model's size.
+    VoxelOutOfBounds {
+        /// The index of the model in `DotVoxData::models`.
+        model: usize,
+        /// The index of the voxel in `Model::voxels`.
+        voxel: usize,
+        /// The position of the voxel.
+        position: [u8; 3],
+        /// The size of the model.
+        size: Size,
+    },
+    /// A voxel's colour index has no colour in the palette, or can't be stored in a .vox file
+    /// (as the file's indices run from 1-255, `Voxel::i` can be at most 254).
+    InvalidColorIndex {
+        /// The index of the model in `DotVoxData::models`.
+        model: usize,
+        /// The index of the voxel in `Model::voxels`.
+        voxel: usize,
+        /// The voxel's colour index, as in `Voxel::i`.
+        index: u8,
+    },
+    /// An entry in `DotVoxData::scene` refers to a model which doesn't exist.
+    SceneModelOutOfRange {
+        /// The index of the entry in `DotVoxData::scene`.
+        entry: usize,
+        /// The model ID of the entry.
+        model_id: usize,
+    },
+    /// A Shape node refers to a model which doesn't exist.
+    ShapeModelOutOfRange {
+        /// The ID of the Shape node.
+        node: u32,
+        /// The model ID of the Shape node.
+        model_id: u32,
+    },
+    /// A Group or Transform node refers to a child node which doesn't exist.
+    MissingChild {
+        /// The ID of the parent node.
+        node: u32,
+        /// The ID of the missing child node.
+        child: u32,
+    },
+    /// The scene graph can't be collapsed as it is, so some of its models are missing from
+    /// `DotVoxData::scene`, as reported by `SceneGraph::try_iter_shapes`. Missing nodes are
+    /// reported as `MissingChild` instead.
+    MalformedSceneGraph(ScanError),
+    /// A model's SIZE chunk wasn't immediately followed by an XYZI chunk in the file, so the
+    /// model was loaded without any voxels.
+    MissingVoxels {
+        /// The index of the model in `DotVoxData::models`.
+        model: usize,
+    },
+    /// An XYZI chunk wasn't immediately preceded by a SIZE chunk in the file, so its voxels were
+    /// dropped and an empty model with a size of zero loaded in their place.
+    MissingSize {
+        /// The index of the model in `DotVoxData::models`.
+        model: usize,
+    },
+    /// The file's PACK chunk declared a different number of models than the file contains.
+    ModelCountMismatch {
+        /// The number of models declared by the PACK chunk.
+        declared: u32,
+        /// The number of models loaded, including any empty ones loaded in place of missing
+        /// chunks.
+        found: usize,
+    },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::VoxelOutOfBounds { model, voxel, position, size } =>
+                write!(f, "Voxel {} of model {} at {:?} lies outside the model's size of {}x{}x{}",
+                       voxel, model, position, size.x, size.y, size.z),
+            ValidationIssue::InvalidColorIndex { model, voxel, index } =>
+                write!(f, "Voxel {} of model {} has an invalid colour index ({})", voxel, model, index),
+            ValidationIssue::SceneModelOutOfRange { entry, model_id } =>
+                write!(f, "Scene entry {} refers to a model which doesn't exist (id: {})", entry, model_id),
+            ValidationIssue::ShapeModelOutOfRange { node, model_id } =>
+                write!(f, "Shape node {} refers to a model which doesn't exist (id: {})", node, model_id),
+            ValidationIssue::MissingChild { node, child } =>
+                write!(f, "Node {} refers to a child node which doesn't exist (id: {})", node, child),
+            ValidationIssue::MalformedSceneGraph(error) => write!(f, "{}", error),
+            ValidationIssue::MissingVoxels { model } =>
+                write!(f, "Model {} has a SIZE chunk but no XYZI chunk", model),
+            ValidationIssue::MissingSize { model } =>
+                write!(f, "Model {} has an XYZI chunk but no SIZE chunk", model),
+            ValidationIssue::ModelCountMismatch { declared, found } =>
+                write!(f, "The PACK chunk declares {} models, but the file contains {}", declared, found),
+        }
+    }
+}
+impl Error for ValidationIssue {}
+
+/// The largest in-memory colour index which can be written to a .vox file.
+const MAX_COLOR_INDEX: u8 = 254;
+
+/// Finds every issue in the supplied data, as described in `DotVoxData::validate`.
+pub fn validate(data: &DotVoxData) -> Vec<ValidationIssue> {
+    let mut issues = data.model_issues.clone();
+    for (model_index, model) in data.models.iter().enumerate() {
+        for (voxel_index, voxel) in model.voxels.iter().enumerate() {
+            if !model.size.contains(voxel) {
+                issues.push(ValidationIssue::VoxelOutOfBounds {
+                    model: model_index,
+                    voxel: voxel_index,
+                    position: [voxel.x, voxel.y, voxel.z],
+                    size: model.size,
+                });
+            }
+            if voxel.i > MAX_COLOR_INDEX || voxel.i as usize >= data.palette.len() {
+                issues.push(ValidationIssue::InvalidColorIndex {
+                    model: model_index,
+                    voxel: voxel_index,
+                    index: voxel.i,
+                });
+            }
+        }
+    }
+
+    for (entry, &(_, model_id)) in data.scene.iter().enumerate() {
+        if model_id >= data.models.len() {
+            issues.push(ValidationIssue::SceneModelOutOfRange { entry, model_id });
+        }
+    }
+
+    let mut nodes: Vec<_> = data.scene_graph.nodes().collect();
+    nodes.sort_by_key(|node| node.id);
+    for node in nodes {
+        let children = match node.kind {
+            NodeKind::Group { ref children_ids } => children_ids.clone(),
+            NodeKind::Transform { child_id, .. } => vec![child_id],
+            NodeKind::Shape { model_id } => {
+                if model_id as usize >= data.models.len() {
+                    issues.push(ValidationIssue::ShapeModelOutOfRange { node: node.id, model_id });
+                }
+                vec![]
+            }
+        };
+        for child in children {
+            if data.scene_graph.get(child).is_none() {
+                issues.push(ValidationIssue::MissingChild { node: node.id, child });
+            }
+        }
+    }
+
+    issues.extend(data.scene_graph.try_iter_shapes()
+        .filter_map(Result::err)
+        .filter(|error| !matches!(error, ScanError::MissingNode(_)))
+        .map(ValidationIssue::MalformedSceneGraph));
+    issues
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use scene::test_nodes::*;
+    use {load, load_bytes, Dict, Voxel};
+
+    #[test]
+    fn valid_files_have_no_issues() {
+        for file in &["placeholder.vox", "placeholder-with-materials.vox", "two-model-scene.vox",
+                      "three-layers.vox"] {
+            let data = load(&format!("src/resources/{}", file)).unwrap();
+            assert_eq!(validate(&data), vec![], "{}", file);
+        }
+    }
+
+    #[test]
+    fn models_recovered_while_loading_are_found() {
+        let mut bytes = include_bytes!("resources/placeholder.vox").to_vec();
+        // Rename the XYZI chunk, so that the model's SIZE chunk has no voxels after it
+        assert_eq!(&bytes[44..48], b"XYZI");
+        bytes[44] = b'x';
+        let data = load_bytes(&bytes).unwrap();
+        assert!(data.models[0].voxels.is_empty());
+        assert_eq!(validate(&data), vec![ValidationIssue::MissingVoxels { model: 0 }]);
+    }
+
+    #[test]
+    fn out_of_bounds_voxels_are_found() {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        data.models[0].voxels.push(Voxel { x: 1, y: 2, z: 0, i: 0 });
+        data.models[0].voxels.push(Voxel { x: 0, y: 0, z: 0, i: 255 });
+        assert_eq!(validate(&data), vec![
+            ValidationIssue::VoxelOutOfBounds {
+                model: 0,
+                voxel: 4,
+                position: [1, 2, 0],
+                size: Size { x: 2, y: 2, z: 2 },
+            },
+            ValidationIssue::InvalidColorIndex { model: 0, voxel: 5, index: 255 },
+        ]);
+
+        data.models[0].retain_valid_voxels();
+        assert_eq!(data.models[0].voxels.len(), 5);
+    }
+
+    #[test]
+    fn colours_missing_from_the_palette_are_found() {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        data.palette.truncate(100);
+        assert_eq!(validate(&data), vec![
+            ValidationIssue::InvalidColorIndex { model: 0, voxel: 0, index: 225 },
+            ValidationIssue::InvalidColorIndex { model: 0, voxel: 1, index: 215 },
+            ValidationIssue::InvalidColorIndex { model: 0, voxel: 2, index: 235 },
+        ]);
+    }
+
+    #[test]
+    fn dangling_scene_references_are_found() {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        data.scene[0].1 = 3;
+        data.scene_graph.add_node(transform_node(10, Dict::new(), 11, [0, 0, 0]));
+        data.scene_graph.add_node(shape_node(12, 1));
+        let issues = validate(&data);
+        assert_eq!(issues, vec![
+            ValidationIssue::SceneModelOutOfRange { entry: 0, model_id: 3 },
+            ValidationIssue::MissingChild { node: 10, child: 11 },
+            ValidationIssue::ShapeModelOutOfRange { node: 12, model_id: 1 },
+            // Node 12 has no parent either, so isn't part of the scene
+            ValidationIssue::MalformedSceneGraph(ScanError::DetachedShape(12)),
+        ]);
+        assert_eq!(data.scene_graph.collapse_to_vec().len(), 1);
+        assert_eq!(
+            issues[1].to_string(),
+            "Node 10 refers to a child node which doesn't exist (id: 11)"
+        );
+    }
+
+    #[test]
+    fn malformed_scene_graphs_are_found() {
+        let mut data = load("src/resources/two-model-scene.vox").unwrap();
+        // Point the second model's Transform node back at the root's group
+        let group_id = match data.scene_graph.get(0).unwrap().kind {
+            NodeKind::Transform { child_id, .. } => child_id,
+            _ => panic!("Expected the root to be a Transform node"),
+        };
+        let shapes = data.scene_graph.iter_shapes().map(|shape| shape.path).collect::<Vec<_>>();
+        let (second, shape) = (shapes[1][2], shapes[1][3]);
+        data.scene_graph.add_node(transform_node(second, Dict::new(), group_id, [0, 0, 0]));
+        data.scene = data.scene_graph.collapse_to_vec();
+        // The second model's Shape node is left without a parent, so isn't part of the scene
+        assert_eq!(data.scene.len(), 1);
+        assert_eq!(validate(&data), vec![
+            ValidationIssue::MalformedSceneGraph(ScanError::DetachedShape(shape)),
+            ValidationIssue::MalformedSceneGraph(ScanError::Cycle(group_id)),
+        ]);
+
+        // A dangling child is only reported once
+        data.scene_graph.add_node(transform_node(second, Dict::new(), 99, [0, 0, 0]));
+        assert_eq!(validate(&data), vec![
+            ValidationIssue::MissingChild { node: second, child: 99 },
+            ValidationIssue::MalformedSceneGraph(ScanError::DetachedShape(shape)),
+        ]);
+    }
+
+    #[test]
+    fn graphs_with_a_non_zero_root_are_valid() {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        let nodes = data.scene_graph.nodes().cloned().collect::<Vec<_>>();
+        data.scene_graph = Default::default();
+        for mut node in nodes {
+            // Shift every node ID up by 10
+            node.id += 10;
+            match &mut node.kind {
+                NodeKind::Transform { child_id, .. } => *child_id += 10,
+                NodeKind::Group { children_ids } => children_ids.iter_mut().for_each(|id| *id += 10),
+                NodeKind::Shape { .. } => (),
+            }
+            data.scene_graph.add_node(node);
+        }
+        assert_eq!(data.scene_graph.roots(), vec![10]);
+        assert_eq!(data.scene_graph.collapse_to_vec(), data.scene);
+        assert_eq!(validate(&data), vec![]);
+    }
+}
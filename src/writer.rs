@@ -0,0 +1,436 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::iter::Peekable;
+use std::vec;
+use {Camera, Dict, DotVoxData, Frame, Layer, Material, Model, Node, NodeKind, RawChunk, SceneGraph, Transform};
+
+const MAGIC_NUMBER: &[u8] = b"VOX ";
+
+/// The RGBA chunk always holds exactly this many colours.
+const PALETTE_SIZE: usize = 256;
+
+pub fn write_vox<W: Write>(data: &DotVoxData, mut writer: W) -> io::Result<()> {
+    let mut children = Children::new(&data.unknown_chunks);
+    for model in &data.models {
+        write_model(children.next(2)?, model)?;
+    }
+    let scene_graph = if data.scene_graph.is_empty() && !data.models.is_empty() {
+        Cow::Owned(default_scene_graph(data)?)
+    } else {
+        Cow::Borrowed(&data.scene_graph)
+    };
+    let mut nodes = scene_graph.nodes().collect::<Vec<_>>();
+    nodes.sort_by_key(|node| node.id);
+    for node in nodes {
+        write_node(children.next(1)?, node)?;
+    }
+    for layer in &data.layers {
+        write_layer(children.next(1)?, layer)?;
+    }
+    write_palette(children.next(1)?, &data.palette)?;
+    if let Some(index_map) = data.index_map {
+        write_chunk(children.next(1)?, "IMAP", &index_map, &[])?;
+    }
+    if !data.palette_notes.is_empty() {
+        write_notes(children.next(1)?, &data.palette_notes)?;
+    }
+    for material in &data.materials {
+        write_material(children.next(1)?, material)?;
+    }
+    for render_object in &data.render_objects {
+        let mut content = Vec::new();
+        write_dict(&mut content, render_object)?;
+        write_chunk(children.next(1)?, "rOBJ", &content, &[])?;
+    }
+    for camera in &data.cameras {
+        write_camera(children.next(1)?, camera)?;
+    }
+    let children = children.finish()?;
+
+    writer.write_all(MAGIC_NUMBER)?;
+    writer.write_u32::<LittleEndian>(data.version)?;
+    write_chunk(&mut writer, "MAIN", &[], &children)
+}
+
+/// The children of the MAIN chunk, which writes each unknown chunk back at its
+/// `RawChunk::position` among the chunks written around it.
+struct Children<'a> {
+    bytes: Vec<u8>,
+    written: usize,
+    unknown_chunks: Peekable<vec::IntoIter<&'a RawChunk>>,
+}
+
+impl<'a> Children<'a> {
+    fn new(unknown_chunks: &'a [RawChunk]) -> Self {
+        let mut unknown_chunks = unknown_chunks.iter().collect::<Vec<_>>();
+        // A stable sort, so that chunks with the same position keep their order
+        unknown_chunks.sort_by_key(|chunk| chunk.position);
+        Children { bytes: Vec::new(), written: 0, unknown_chunks: unknown_chunks.into_iter().peekable() }
+    }
+
+    /// Writes the unknown chunks which come before the next `count` chunks, returning the
+    /// buffer to write those chunks to.
+    fn next(&mut self, count: usize) -> io::Result<&mut Vec<u8>> {
+        loop {
+            let written = self.written;
+            match self.unknown_chunks.next_if(|chunk| chunk.position <= written) {
+                Some(chunk) => write_unknown_chunk(&mut self.bytes, chunk)?,
+                None => break,
+            }
+            self.written += 1;
+        }
+        self.written += count;
+        Ok(&mut self.bytes)
+    }
+
+    /// Writes the remaining unknown chunks, returning every chunk written.
+    fn finish(mut self) -> io::Result<Vec<u8>> {
+        for chunk in self.unknown_chunks {
+            write_unknown_chunk(&mut self.bytes, chunk)?;
+        }
+        Ok(self.bytes)
+    }
+}
+
+fn write_unknown_chunk<W: Write>(writer: &mut W, chunk: &RawChunk) -> io::Result<()> {
+    if chunk.id.len() != 4 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk IDs must be 4 bytes long"));
+    }
+    write_chunk(writer, &chunk.id, &chunk.content, &chunk.children)
+}
+
+/// The scene graph written for data without one, as MagicaVoxel won't show models which aren't
+/// in its scene graph: a root Transform node holding a Group node, which holds a Transform node
+/// and a Shape node for each entry in `scene`, or for each model (without moving it) if `scene` is
+/// empty too.
+fn default_scene_graph(data: &DotVoxData) -> io::Result<SceneGraph> {
+    let entries = if data.scene.is_empty() {
+        (0..data.models.len()).map(|model_id| (Transform::default(), model_id)).collect()
+    } else {
+        data.scene.clone()
+    };
+    // MagicaVoxel puts every node other than the root on layer 0, but only if there is one
+    let layer_id = if data.layers.iter().any(|layer| layer.id == 0) { 0 } else { u32::MAX };
+    let transform_node = |id, child_id, layer_id, transform: &Transform| -> io::Result<Node> {
+        let attributes = transform.to_dict().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "scene rotations must be signed permutation matrices")
+        })?;
+        let frames = vec![Frame { attributes }];
+        Ok(Node { id, attributes: Dict::new(), kind: NodeKind::Transform { child_id, layer_id, frames } })
+    };
+
+    let mut graph = SceneGraph::new();
+    graph.add_node(transform_node(0, 1, u32::MAX, &Transform::default())?);
+    let children_ids = (0..entries.len() as u32).map(|entry| 2 + 2 * entry).collect();
+    graph.add_node(Node { id: 1, attributes: Dict::new(), kind: NodeKind::Group { children_ids } });
+    for (entry, (transform, model_id)) in entries.iter().enumerate() {
+        let id = 2 + 2 * entry as u32;
+        graph.add_node(transform_node(id, id + 1, layer_id, transform)?);
+        let kind = NodeKind::Shape { model_id: *model_id as u32 };
+        graph.add_node(Node { id: id + 1, attributes: Dict::new(), kind });
+    }
+    Ok(graph)
+}
+
+fn write_chunk<W: Write>(writer: &mut W, id: &str, content: &[u8], children: &[u8]) -> io::Result<()> {
+    writer.write_all(id.as_bytes())?;
+    writer.write_u32::<LittleEndian>(content.len() as u32)?;
+    writer.write_u32::<LittleEndian>(children.len() as u32)?;
+    writer.write_all(content)?;
+    writer.write_all(children)
+}
+
+fn write_model<W: Write>(writer: &mut W, model: &Model) -> io::Result<()> {
+    let mut size = Vec::with_capacity(12);
+    size.write_u32::<LittleEndian>(model.size.x)?;
+    size.write_u32::<LittleEndian>(model.size.y)?;
+    size.write_u32::<LittleEndian>(model.size.z)?;
+    write_chunk(writer, "SIZE", &size, &[])?;
+
+    let mut voxels = Vec::with_capacity(4 + model.voxels.len() * 4);
+    voxels.write_u32::<LittleEndian>(model.voxels.len() as u32)?;
+    for voxel in &model.voxels {
+        // Undo the shift to in-memory palette indices applied when parsing, which leaves no room
+        // for index 255
+        let i = voxel.i.checked_add(1).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "voxel palette indices must be at most 254")
+        })?;
+        voxels.write_all(&[voxel.x, voxel.y, voxel.z, i])?;
+    }
+    write_chunk(writer, "XYZI", &voxels, &[])
+}
+
+fn write_node<W: Write>(writer: &mut W, node: &Node) -> io::Result<()> {
+    let mut content = Vec::new();
+    content.write_u32::<LittleEndian>(node.id)?;
+    write_dict(&mut content, &node.attributes)?;
+    let id = match &node.kind {
+        NodeKind::Transform { child_id, layer_id, frames } => {
+            content.write_u32::<LittleEndian>(*child_id)?;
+            content.write_i32::<LittleEndian>(-1)?; // reserved id
+            content.write_u32::<LittleEndian>(*layer_id)?;
+            content.write_u32::<LittleEndian>(frames.len() as u32)?;
+            for frame in frames {
+                write_dict(&mut content, &frame.attributes)?;
+            }
+            "nTRN"
+        }
+        NodeKind::Group { children_ids } => {
+            content.write_u32::<LittleEndian>(children_ids.len() as u32)?;
+            for child_id in children_ids {
+                content.write_u32::<LittleEndian>(*child_id)?;
+            }
+            "nGRP"
+        }
+        NodeKind::Shape { model_id } => {
+            content.write_u32::<LittleEndian>(1)?; // number of models
+            content.write_u32::<LittleEndian>(*model_id)?;
+            write_dict(&mut content, &Dict::new())?; // model attributes
+            "nSHP"
+        }
+    };
+    write_chunk(writer, id, &content, &[])
+}
+
+fn write_layer<W: Write>(writer: &mut W, layer: &Layer) -> io::Result<()> {
+    let mut content = Vec::new();
+    content.write_u32::<LittleEndian>(layer.id)?;
+    write_dict(&mut content, &layer.attributes)?;
+    content.write_i32::<LittleEndian>(-1)?; // reserved id
+    write_chunk(writer, "LAYR", &content, &[])
+}
+
+fn write_notes<W: Write>(writer: &mut W, notes: &[String]) -> io::Result<()> {
+    let mut content = Vec::new();
+    content.write_u32::<LittleEndian>(notes.len() as u32)?;
+    for note in notes {
+        write_string(&mut content, note)?;
+    }
+    write_chunk(writer, "NOTE", &content, &[])
+}
+
+fn write_camera<W: Write>(writer: &mut W, camera: &Camera) -> io::Result<()> {
+    let mut content = Vec::new();
+    content.write_u32::<LittleEndian>(camera.id)?;
+    write_dict(&mut content, &camera.attributes)?;
+    write_chunk(writer, "rCAM", &content, &[])
+}
+
+fn write_palette<W: Write>(writer: &mut W, palette: &[u32]) -> io::Result<()> {
+    let mut colours = Vec::with_capacity(PALETTE_SIZE * 4);
+    for i in 0..PALETTE_SIZE {
+        colours.write_u32::<LittleEndian>(palette.get(i).cloned().unwrap_or(0))?;
+    }
+    write_chunk(writer, "RGBA", &colours, &[])
+}
+
+fn write_material<W: Write>(writer: &mut W, material: &Material) -> io::Result<()> {
+    let mut content = Vec::new();
+    content.write_u32::<LittleEndian>(material.id)?;
+    write_dict(&mut content, &material.properties)?;
+    write_chunk(writer, "MATL", &content, &[])
+}
+
+fn write_dict<W: Write>(writer: &mut W, dict: &Dict) -> io::Result<()> {
+    // Sort the entries so that the output doesn't depend on the map's iteration order
+    let mut entries = dict.iter().collect::<Vec<_>>();
+    entries.sort();
+    writer.write_u32::<LittleEndian>(entries.len() as u32)?;
+    for (key, value) in entries {
+        write_string(writer, key)?;
+        write_string(writer, value)?;
+    }
+    Ok(())
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(string.len() as u32)?;
+    writer.write_all(string.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian};
+    use std::fs;
+    use {load, load_bytes, parse_chunk, NodeKind, RawChunk, SceneGraph, Transform};
+
+    fn round_trip(filename: &str) {
+        let original = load(filename).unwrap();
+        let mut buffer = Vec::new();
+        original.write_vox(&mut buffer).unwrap();
+
+        // MAIN has no content of its own, and its children span the rest of the file
+        assert_eq!(&buffer[8..12], b"MAIN");
+        assert_eq!(LittleEndian::read_u32(&buffer[12..16]), 0);
+        assert_eq!(LittleEndian::read_u32(&buffer[16..20]) as usize, buffer.len() - 20);
+
+        let written = load_bytes(&buffer).unwrap();
+        assert_eq!(written.version, original.version);
+        assert_eq!(written.models, original.models);
+        assert_eq!(written.palette, original.palette);
+        assert_eq!(written.palette_notes, original.palette_notes);
+        assert_eq!(written.index_map, original.index_map);
+        assert_eq!(written.materials, original.materials);
+        if original.scene_graph.is_empty() {
+            // A scene graph is made up showing each model, as checked below
+            assert_eq!(written.scene.len(), original.models.len());
+        } else {
+            assert_eq!(written.scene, original.scene);
+            assert_eq!(written.scene_graph, original.scene_graph);
+        }
+        assert_eq!(written.layers, original.layers);
+        assert_eq!(written.render_objects, original.render_objects);
+        assert_eq!(written.cameras, original.cameras);
+        assert_eq!(written.unknown_chunks, original.unknown_chunks);
+    }
+
+    #[test]
+    fn placeholder_survives_a_round_trip() {
+        round_trip("src/resources/placeholder.vox");
+    }
+
+    #[test]
+    fn materials_survive_a_round_trip() {
+        round_trip("src/resources/placeholder-with-materials.vox");
+    }
+
+    #[test]
+    fn multiple_models_survive_a_round_trip() {
+        round_trip("src/resources/two-model-scene.vox");
+    }
+
+    #[test]
+    fn layers_survive_a_round_trip() {
+        round_trip("src/resources/three-layers.vox");
+    }
+
+    #[test]
+    fn palette_notes_survive_a_round_trip() {
+        round_trip("src/resources/palette-notes.vox");
+    }
+
+    #[test]
+    fn index_maps_survive_a_round_trip() {
+        round_trip("src/resources/index-map.vox");
+    }
+
+    fn chunk_ids(bytes: &[u8]) -> Vec<String> {
+        let (main, _) = parse_chunk(&bytes[8..]).unwrap();
+        main.parse_children().unwrap().into_iter().map(|chunk| chunk.id).collect()
+    }
+
+    #[test]
+    fn unknown_chunks_survive_a_round_trip() {
+        round_trip("src/resources/extension-chunks.vox");
+        round_trip("src/resources/metal-material.vox");
+
+        // Saved by MagicaVoxel, with its render settings in chunks this crate doesn't recognise
+        let bytes = fs::read("src/resources/metal-material.vox").unwrap();
+        let mut buffer = Vec::new();
+        load_bytes(&bytes).unwrap().write_vox(&mut buffer).unwrap();
+        assert_eq!(chunk_ids(&buffer), chunk_ids(&bytes));
+
+        // The scene graph written for the model goes after the chunks following it in the file
+        let mut buffer = Vec::new();
+        load("src/resources/extension-chunks.vox").unwrap().write_vox(&mut buffer).unwrap();
+        assert_eq!(&chunk_ids(&buffer)[..5], &["SIZE", "XYZI", "XTRA", "xEMP", "nTRN"]);
+    }
+
+    #[test]
+    fn chunks_which_fail_to_parse_keep_their_place() {
+        let bytes = fs::read("src/resources/placeholder.vox").unwrap();
+        let (main, _) = parse_chunk(&bytes[8..]).unwrap();
+        let mut children = main.parse_children().unwrap();
+        // A MATL chunk with a material ID but no properties, followed by an unknown chunk
+        children.insert(2, RawChunk { id: "MATL".to_owned(), content: vec![1, 0, 0, 0], children: vec![], position: 0 });
+        children.insert(3, RawChunk { id: "xTST".to_owned(), content: vec![7], children: vec![], position: 0 });
+        let mut content = Vec::new();
+        for chunk in &children {
+            super::write_chunk(&mut content, &chunk.id, &chunk.content, &chunk.children).unwrap();
+        }
+        let mut file = bytes[..8].to_vec();
+        super::write_chunk(&mut file, "MAIN", &[], &content).unwrap();
+
+        let data = load_bytes(&file).unwrap();
+        let unknown: Vec<(&str, usize)> = data.unknown_chunks.iter()
+            .map(|chunk| (chunk.id.as_str(), chunk.position))
+            .collect();
+        assert_eq!(&unknown[..2], &[("MATL", 2), ("xTST", 3)]);
+        let mut buffer = Vec::new();
+        data.write_vox(&mut buffer).unwrap();
+        assert_eq!(&chunk_ids(&buffer)[..5], &["SIZE", "XYZI", "MATL", "xTST", "nTRN"]);
+        assert_eq!(load_bytes(&buffer).unwrap().unknown_chunks, data.unknown_chunks);
+    }
+
+    #[test]
+    fn palette_index_255_is_rejected() {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        data.models[0].voxels[0].i = 254;
+        let mut buffer = Vec::new();
+        data.write_vox(&mut buffer).unwrap();
+        assert_eq!(load_bytes(&buffer).unwrap().models[0].voxels[0].i, 254);
+
+        data.models[0].voxels[0].i = 255;
+        let error = data.write_vox(Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), ::std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn unknown_chunks_must_have_four_byte_ids() {
+        let mut data = load("src/resources/extension-chunks.vox").unwrap();
+        data.unknown_chunks[0].id = "XTRA2".to_owned();
+        let error = data.write_vox(Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), ::std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn cameras_survive_a_round_trip() {
+        round_trip("src/resources/cameras.vox");
+    }
+
+    #[test]
+    fn a_scene_graph_is_written_for_data_without_one() {
+        let mut data = load("src/resources/two-model-scene.vox").unwrap();
+        let scene = data.scene.clone();
+        data.scene_graph = SceneGraph::new();
+        let mut buffer = Vec::new();
+        data.write_vox(&mut buffer).unwrap();
+        let written = load_bytes(&buffer).unwrap();
+        assert_eq!(written.scene, scene);
+        assert_eq!(written.scene_graph.roots(), vec![0]);
+        assert_eq!(written.scene_graph.len(), 6);
+
+        // Without a scene either, every model is shown where it is
+        data.scene.clear();
+        data.layers.clear();
+        let mut buffer = Vec::new();
+        data.write_vox(&mut buffer).unwrap();
+        let written = load_bytes(&buffer).unwrap();
+        assert_eq!(written.scene, vec![(Transform::default(), 0), (Transform::default(), 1)]);
+        assert_eq!(written.validate(), vec![]);
+        assert!(written.scene_graph.nodes().all(|node| match node.kind {
+            NodeKind::Transform { layer_id, .. } => layer_id == u32::MAX,
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn scenes_which_cannot_be_written_are_rejected() {
+        let mut data = load("src/resources/placeholder.vox").unwrap();
+        data.scene_graph = SceneGraph::new();
+        data.scene[0].0.r = [[2, 0, 0], [0, 1, 0], [0, 0, 1]];
+        let error = data.write_vox(Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), ::std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn saved_files_can_be_loaded() {
+        let data = load("src/resources/placeholder-with-materials.vox").unwrap();
+        let path = ::std::env::temp_dir().join(format!("dot_vox-save-{}.vox", ::std::process::id()));
+        data.save(&path).unwrap();
+        let saved = load(path.to_str().unwrap());
+        ::std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.unwrap(), data);
+    }
+}